@@ -1,7 +1,25 @@
+use std::process::exit;
+
 use clap::Parser;
+use serde::Serialize;
 
 use crate::mode::Mode;
 
+/// Logs and prints an error, then exits
+pub fn fail(msg: &str) -> ! {
+    log::error!("{}", msg);
+    eprintln!("{}", msg);
+    exit(1);
+}
+
+/// Prints a value to stdout as pretty json
+pub fn print_json<T: Serialize>(value: &T) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("Couldn't serialize to json!")
+    );
+}
+
 /// This is used recursively to process the argument tree
 pub trait Actionable {
     /// Performs the action
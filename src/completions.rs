@@ -12,13 +12,12 @@ pub struct Completions {
 }
 
 impl Actionable for Completions {
-    fn perform(&self, dryrun: bool) {
+    /// Completions are only written to stdout, so they are generated even in dryrun
+    fn perform(&self, _dryrun: bool) {
         let mut app = cli::Args::command();
         let mut buf = io::stdout();
 
         log::info!("Generating completions");
-        if !dryrun {
-            clap_complete::generate(self.shell, &mut app, "nf", &mut buf);
-        }
+        clap_complete::generate(self.shell, &mut app, "nf", &mut buf);
     }
 }
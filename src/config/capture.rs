@@ -0,0 +1,251 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use regex::Regex;
+
+use crate::{
+    nest,
+    plan::FsPlan,
+    templates::{self, placeholders::Variables},
+};
+
+/// Files that make up a project's flake setup, including the nest dir. Missing ones are skipped.
+fn flake_files() -> Vec<PathBuf> {
+    vec![
+        PathBuf::from("flake.nix"),
+        PathBuf::from("flake.lock"),
+        nest::nest_dir(),
+        PathBuf::from(".envrc"),
+    ]
+}
+
+/// Reads the project name from the CWD's Cargo.toml, package.json or pyproject.toml, falling
+/// back to the name of the CWD
+pub fn detect_project_name() -> Option<String> {
+    let from_toml = |file: &str, keys: &[&[&str]]| -> Option<String> {
+        let table: toml::Table = fs::read_to_string(file).ok()?.parse().ok()?;
+        keys.iter().find_map(|path| {
+            let mut value = table.get(path[0])?;
+            for key in &path[1..] {
+                value = value.get(key)?;
+            }
+            value.as_str().map(|s| s.to_string())
+        })
+    };
+
+    from_toml("Cargo.toml", &[&["package", "name"]])
+        .or_else(|| {
+            let json: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string("package.json").ok()?).ok()?;
+            json.get("name")?.as_str().map(|s| s.to_string())
+        })
+        .or_else(|| {
+            from_toml(
+                "pyproject.toml",
+                &[&["project", "name"], &["tool", "poetry", "name"]],
+            )
+        })
+        .or_else(|| {
+            std::env::current_dir()
+                .ok()?
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+        })
+}
+
+/// Finds the nixpkgs branch a flake follows, e.g. github:nixos/nixpkgs/nixos-unstable
+pub fn detect_nixpkgs_branch(flake: &str) -> Option<String> {
+    let regex = Regex::new(r"github:(?i:nixos)/nixpkgs/([\w.-]+)").unwrap();
+    regex.captures(flake).map(|c| c[1].to_string())
+}
+
+/// Values found in the project that can be turned back into placeholders
+pub fn detect_variables() -> Variables {
+    let mut out = Variables::new();
+
+    if let Some(name) = detect_project_name() {
+        out.insert("project_name".to_string(), name);
+    }
+
+    let flake = [
+        PathBuf::from("flake.nix"),
+        nest::nest_dir().join("flake.nix"),
+    ]
+    .iter()
+    .find_map(|f| fs::read_to_string(f).ok());
+    if let Some(branch) = flake.as_deref().and_then(detect_nixpkgs_branch) {
+        out.insert("nixpkgs_branch".to_string(), branch);
+    }
+
+    out
+}
+
+/// Whether c could continue a name like flake-utils or rust_overlay
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Replaces standalone occurrences of each value with its {{placeholder}}, longest values first.
+/// Occurrences that are part of a longer name, like rust in rust-overlay, are left alone.
+pub fn placeholderize(text: &str, variables: &Variables) -> String {
+    let mut variables: Vec<_> = variables.iter().filter(|(_, v)| v.len() > 1).collect();
+    variables.sort_by_key(|(_, v)| std::cmp::Reverse(v.len()));
+
+    let mut out = text.to_string();
+    for (name, value) in variables {
+        let mut replaced = String::with_capacity(out.len());
+        let mut last = 0;
+
+        for (start, _) in out.match_indices(value.as_str()) {
+            let end = start + value.len();
+            let standalone = !out[..start].chars().next_back().is_some_and(is_name_char)
+                && !out[end..].chars().next().is_some_and(is_name_char);
+
+            if standalone {
+                replaced.push_str(&out[last..start]);
+                replaced.push_str(&format!("{{{{{}}}}}", name));
+                last = end;
+            }
+        }
+
+        replaced.push_str(&out[last..]);
+        out = replaced;
+    }
+
+    out
+}
+
+/// Plans copying a single file, turning detected values into placeholders if there are any
+fn plan_file(
+    from: &Path,
+    to: &Path,
+    variables: Option<&Variables>,
+    plan: &mut FsPlan,
+) -> Result<()> {
+    let is_lock = from.extension().is_some_and(|e| e == "lock");
+
+    match (variables, is_lock) {
+        (Some(variables), false) => match String::from_utf8(fs::read(from)?) {
+            Ok(contents) => plan.write(to, placeholderize(&contents, variables).into_bytes()),
+            Err(_) => plan.copy(from, to),
+        },
+        _ => plan.copy(from, to),
+    }
+
+    Ok(())
+}
+
+/// Plans copying a file or directory from the CWD into the template
+fn plan_path(
+    from: &Path,
+    to: &Path,
+    variables: Option<&Variables>,
+    plan: &mut FsPlan,
+) -> Result<()> {
+    if !from.is_dir() {
+        return plan_file(from, to, variables, plan);
+    }
+
+    plan.mkdir(to);
+
+    let mut entries = fs::read_dir(from)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        plan_path(&path, &to.join(path.file_name().unwrap()), variables, plan)?;
+    }
+
+    Ok(())
+}
+
+/// Plans snapshotting the CWD's flake files, plus any extra includes, into dest
+pub fn plan_capture(
+    includes: &[PathBuf],
+    dest: &Path,
+    variables: Option<&Variables>,
+    plan: &mut FsPlan,
+) -> Result<()> {
+    let nest = nest::nest_dir();
+    let mut sources: Vec<PathBuf> = flake_files().into_iter().filter(|p| p.exists()).collect();
+
+    if !sources
+        .iter()
+        .any(|p| p.ends_with("flake.nix") || p == &nest)
+    {
+        bail!(
+            "There is no flake.nix or {}/ in the CWD to capture",
+            nest.display()
+        );
+    }
+
+    for include in includes {
+        if !include.exists() {
+            bail!("{} doesn't exist", include.display());
+        }
+        if include.is_absolute() || include.starts_with("..") {
+            bail!("{} must be a path inside the CWD", include.display());
+        }
+        sources.push(include.clone());
+    }
+
+    // The nest dir is stored in the layout that templates use
+    plan.mkdir(dest);
+    for source in sources {
+        let to = match source.strip_prefix(&nest) {
+            Ok(rest) => Path::new(templates::NEST_DIR)
+                .components()
+                .chain(rest.components())
+                .collect(),
+            Err(_) => source.clone(),
+        };
+        plan_path(&source, &dest.join(to), variables, plan)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::templates::placeholders::Variables;
+
+    use super::{detect_nixpkgs_branch, placeholderize};
+
+    #[test]
+    fn nixpkgs_branch() {
+        assert_eq!(
+            detect_nixpkgs_branch(r#"nixpkgs.url = "github:NixOS/nixpkgs/release-25.05";"#),
+            Some("release-25.05".to_string())
+        );
+        assert_eq!(detect_nixpkgs_branch(r#"nixpkgs.url = "nixpkgs";"#), None);
+    }
+
+    #[test]
+    fn placeholders() {
+        let variables = Variables::from([
+            ("project_name".to_string(), "nf".to_string()),
+            ("nixpkgs_branch".to_string(), "nixos-unstable".to_string()),
+        ]);
+
+        assert_eq!(
+            placeholderize(
+                r#"url = "github:nixos/nixpkgs/nixos-unstable"; name = "nf"; inferred = 1;"#,
+                &variables
+            ),
+            r#"url = "github:nixos/nixpkgs/{{nixpkgs_branch}}"; name = "{{project_name}}"; inferred = 1;"#
+        );
+
+        let variables = Variables::from([("project_name".to_string(), "flake".to_string())]);
+        assert_eq!(
+            placeholderize(
+                r#"inputs.flake-utils.url = "github:numtide/flake-utils"; pname = "flake";"#,
+                &variables
+            ),
+            r#"inputs.flake-utils.url = "github:numtide/flake-utils"; pname = "{{project_name}}";"#
+        );
+    }
+}
@@ -1,32 +1,105 @@
-use std::{fs, path::PathBuf};
+use std::path::PathBuf;
 
 use clap::{Args, Subcommand};
 
-use crate::cli::Actionable;
+use crate::cli::{Actionable, fail};
 use crate::completions::Completions;
-use crate::config::manager::get_config_dir;
+use crate::config::manager::get_template_dir;
+use crate::plan::FsPlan;
 
-use super::initialize;
+use super::{capture, initialize};
 
 #[derive(Debug, Args)]
 struct Add {
-    /// Path of the template to add
+    /// Path of the template to add. Either a directory, or a single file which is added as the
+    /// template's flake.nix
     template: PathBuf,
 
     /// Name to add the template under
     name: String,
+
+    /// Replaces any existing template with the same name
+    #[arg(long, default_value_t = false)]
+    force: bool,
 }
 
 impl Actionable for Add {
     fn perform(&self, dryrun: bool) {
-        let dest = get_config_dir().join(&self.name);
-        assert!(!dest.is_file(), "That template already exists!");
+        let dest = get_template_dir().join(&self.name);
+        let mut plan = FsPlan::journaled(format!("config add {}", self.name));
+
+        if dest.exists() {
+            if !self.force {
+                fail(&format!(
+                    "The template {} already exists. Pass --force to replace it.",
+                    self.name
+                ));
+            }
+
+            plan.remove(&dest);
+        }
 
-        if dryrun {
-            println!("{:?} -> {:?}", self.template, dest);
+        if self.template.is_dir() {
+            plan.copy_dir(&self.template, &dest)
+                .expect("Couldn't read template directory!");
+        } else if self.template.is_file() {
+            plan.mkdir(&dest);
+            plan.copy(&self.template, dest.join("flake.nix"));
         } else {
-            fs::copy(&self.template, dest).expect("Couldn't copy template file!");
+            fail(&format!("{} doesn't exist.", self.template.display()));
         }
+
+        plan.run(dryrun).expect("Couldn't add template!");
+    }
+}
+
+#[derive(Debug, Args)]
+struct Capture {
+    /// Name to save the template under
+    name: String,
+
+    /// Extra files or directories from the CWD to include in the template
+    #[arg(long)]
+    include: Vec<PathBuf>,
+
+    /// Turns values detected in the project, such as the project name from Cargo.toml, back into
+    /// {{placeholders}}
+    #[arg(long, default_value_t = false)]
+    placeholders: bool,
+
+    /// Replaces any existing template with the same name
+    #[arg(long, default_value_t = false)]
+    force: bool,
+}
+
+impl Actionable for Capture {
+    fn perform(&self, dryrun: bool) {
+        let dest = get_template_dir().join(&self.name);
+        let mut plan = FsPlan::journaled(format!("config capture {}", self.name));
+
+        if dest.exists() {
+            if !self.force {
+                fail(&format!(
+                    "The template {} already exists. Pass --force to replace it.",
+                    self.name
+                ));
+            }
+
+            plan.remove(&dest);
+        }
+
+        let variables = self.placeholders.then(capture::detect_variables);
+        if let Some(variables) = &variables {
+            for (name, value) in variables {
+                println!("Replacing \"{}\" with {{{{{}}}}}", value, name);
+            }
+        }
+
+        if let Err(e) = capture::plan_capture(&self.include, &dest, variables.as_ref(), &mut plan) {
+            fail(&format!("Couldn't capture the CWD: {}", e));
+        }
+
+        plan.run(dryrun).expect("Couldn't capture template!");
     }
 }
 
@@ -38,13 +111,14 @@ struct Remove {
 
 impl Actionable for Remove {
     fn perform(&self, dryrun: bool) {
-        let target = get_config_dir().join(&self.template);
-
-        if dryrun {
-            println!("Deleting template {} at {:?}", self.template, target);
-        } else {
-            fs::remove_file(&target).expect("Failed to delete template!");
+        let target = get_template_dir().join(&self.template);
+        if !target.exists() {
+            fail(&format!("The template {} doesn't exist.", self.template));
         }
+
+        let mut plan = FsPlan::journaled(format!("config remove {}", self.template));
+        plan.remove(target);
+        plan.run(dryrun).expect("Failed to delete template!");
     }
 }
 
@@ -53,8 +127,8 @@ struct Create;
 
 impl Actionable for Create {
     fn perform(&self, dryrun: bool) {
-        if dryrun {}
-        initialize::initialize_defaults();
+        initialize::initialize_defaults(dryrun)
+            .expect("Failed to initialize default configuration!");
     }
 }
 
@@ -73,6 +147,8 @@ enum Action {
     Add(Add),
     /// Removes a template from ~/.config/nf/templates
     Remove(Remove),
+    /// Saves the CWD's flake files as a new template in ~/.config/nf/templates
+    Capture(Capture),
     /// Generates default config, usually at ~/.config/nf
     Create(Create),
     /// Deletes the existing configuration
@@ -86,6 +162,7 @@ impl Actionable for Action {
         match self {
             Action::Add(add) => add.perform(dryrun),
             Action::Remove(remove) => remove.perform(dryrun),
+            Action::Capture(capture) => capture.perform(dryrun),
             Action::Create(create) => create.perform(dryrun),
             Action::Destroy(destroy) => destroy.perform(dryrun),
             Action::Completions(completions) => completions.perform(dryrun),
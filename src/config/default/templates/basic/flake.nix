@@ -1,6 +1,8 @@
 {
+  description = "{{description}}";
+
   inputs = {
-    nixpkgs = { url = "github:NixOS/nixpkgs/nixos-unstable"; };
+    nixpkgs = { url = "github:NixOS/nixpkgs/{{nixpkgs_branch}}"; };
     flake-utils.url = "github:numtide/flake-utils";
   };
 
@@ -14,6 +16,7 @@
       with pkgs;
       {
         devShells.default = mkShell {
+          name = "{{project_name}}";
           buildInputs = with pkgs; [
           ];
         };
@@ -1,6 +1,8 @@
 {
+  description = "{{description}}";
+
   inputs = {
-    nixpkgs.url = "github:nixos/nixpkgs/release-25.05";
+    nixpkgs.url = "github:nixos/nixpkgs/{{nixpkgs_branch}}";
     flake-utils.url  = "github:numtide/flake-utils";
   };
 
@@ -19,10 +21,11 @@
           inherit name version;
 
           cargoLock.lockFile = ./Cargo.lock;
-          src = self;
+          src = ./.;
         };
 
         devShells.default = pkgs.mkShell {
+          name = "{{project_name}}";
           buildInputs = with pkgs; [
             git
             rustc
@@ -1,47 +1,40 @@
-use std::{fs, io::Result, path::PathBuf};
+use std::path::Path;
 
-use include_dir::{Dir, DirEntry, File, include_dir};
+use anyhow::Result;
+use include_dir::{Dir, DirEntry, include_dir};
 
-use crate::config::manager::get_config_dir;
+use crate::{config::manager::get_config_dir, plan::FsPlan};
 
 static DEFAULT_CONFIG: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/src/config/default");
 
-/// Initializes a default config file
-fn init_file(file: &File<'_>, to: &PathBuf) -> Result<()> {
-    let file_name = file.path().file_name().unwrap();
-    let file_path = to.join(file_name);
-
-    fs::write(&file_path, file.contents())?;
-    Ok(())
-}
-
-/// Recursively initializes the default config
-fn init_recursive(from: &Dir<'_>, to: &PathBuf) -> Result<()> {
+/// Recursively plans the initialization of the default config
+fn plan_recursive(from: &Dir<'_>, to: &Path, plan: &mut FsPlan) {
     for entry in from.entries() {
         match entry {
             DirEntry::Dir(dir) => {
                 let to = to.join(dir.path().file_name().unwrap());
-                fs::create_dir(&to)?;
-                init_recursive(dir, &to)?;
+                plan.mkdir(&to);
+                plan_recursive(dir, &to, plan);
+            }
+            DirEntry::File(file) => {
+                let file_name = file.path().file_name().unwrap();
+                plan.write(to.join(file_name), file.contents().to_vec());
             }
-            DirEntry::File(file) => init_file(&file, &to)?,
         }
     }
-
-    Ok(())
 }
 
 /// Creates default configuration on disk
-pub fn initialize_defaults() {
+pub fn initialize_defaults(dryrun: bool) -> Result<()> {
     let dest = get_config_dir();
 
     log::debug!("Attempting to generate default config at {:?}", dest);
-    log::warn!("Warning! This command does actually generate the config, even in dryrun mode.");
-
     assert!(!dest.is_dir(), "Configuration directory already exists!");
-    fs::create_dir(&dest).expect("Couldn't create empty configuration directory!");
 
-    init_recursive(&DEFAULT_CONFIG, &dest).expect("Failed to initialize default configuration!");
+    let mut plan = FsPlan::new();
+    plan.mkdir(&dest);
+    plan_recursive(&DEFAULT_CONFIG, &dest, &mut plan);
+    plan.run(dryrun)
 }
 
 /// Destroys any existing configuration
@@ -49,9 +42,7 @@ pub fn destroy_configuration(dryrun: bool) -> Result<()> {
     let cdir = get_config_dir();
 
     log::debug!("Removing all of {:?}", cdir);
-    if !dryrun {
-        fs::remove_dir_all(cdir)?;
-    }
-
-    Ok(())
+    let mut plan = FsPlan::journaled("config destroy");
+    plan.remove(cdir);
+    plan.run(dryrun)
 }
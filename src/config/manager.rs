@@ -1,5 +1,9 @@
 use std::io;
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::PathBuf,
+};
 
 use serde::Deserialize;
 
@@ -14,10 +18,10 @@ pub fn map_templates() -> io::Result<HashMap<String, PathBuf>> {
         let entry = entry?;
         let full_path = entry.path().canonicalize()?;
 
-        if full_path.is_dir() {
-            if let Some(filename) = entry.file_name().to_str() {
-                templates.insert(filename.to_string(), full_path);
-            }
+        if full_path.is_dir()
+            && let Some(filename) = entry.file_name().to_str()
+        {
+            templates.insert(filename.to_string(), full_path);
         }
     }
 
@@ -38,9 +42,59 @@ pub fn get_template_dir() -> PathBuf {
 pub struct ConfigFile {
     pub shell: String,
     pub nested_flakes: bool,
+    /// How many filesystem changing commands `nf undo` can revert
+    #[serde(default = "ConfigFile::default_undo_limit")]
+    pub undo_limit: usize,
+    /// Directory that nest moves the flake into, relative to the project
+    #[serde(default = "ConfigFile::default_nest_dir")]
+    pub nest_dir: PathBuf,
+    /// Paths that nest and unnest move along with flake.nix and flake.lock, e.g. .envrc
+    #[serde(default)]
+    pub nest_companions: Vec<PathBuf>,
+    /// Directories that shell and develop look for a nested flake in, in order. Defaults to
+    /// nest_dir
+    #[serde(default)]
+    pub flake_search_paths: Vec<PathBuf>,
+    /// Marks files created by init and nest with git add --intent-to-add without asking
+    #[serde(default)]
+    pub git_intent_to_add: bool,
+    /// Takes packages for run, shell and develop from the nixpkgs in the project's flake.lock
+    #[serde(default)]
+    pub use_project_nixpkgs: bool,
+    /// Flake reference that run, shell and develop take packages from
+    #[serde(default = "ConfigFile::default_nixpkgs")]
+    pub nixpkgs: String,
+    /// Maps project files, e.g. Cargo.toml, to the template `nf init` picks for them
+    #[serde(default)]
+    pub detect: BTreeMap<String, String>,
+    /// Maps project directories of a monorepo to the directory of the flake they use. Relative
+    /// flake directories are resolved from the project.
+    #[serde(default)]
+    pub projects: BTreeMap<String, PathBuf>,
 }
 
 impl ConfigFile {
+    pub fn default_undo_limit() -> usize {
+        10
+    }
+
+    pub fn default_nest_dir() -> PathBuf {
+        PathBuf::from("flake")
+    }
+
+    pub fn default_nixpkgs() -> String {
+        "nixpkgs".to_string()
+    }
+
+    /// Directories to look for a nested flake in, in order
+    pub fn search_paths(&self) -> Vec<PathBuf> {
+        if self.flake_search_paths.is_empty() {
+            vec![self.nest_dir.clone()]
+        } else {
+            self.flake_search_paths.clone()
+        }
+    }
+
     pub fn new() -> anyhow::Result<Self> {
         let file = get_config_dir().join("config.toml");
         let contents = fs::read_to_string(file)?;
@@ -1,3 +1,4 @@
+mod capture;
 pub mod command;
 mod initialize;
 pub mod manager;
@@ -0,0 +1,12 @@
+use similar::TextDiff;
+
+/// Renders a unified diff between two versions of a file. Binary files only get a summary.
+pub fn unified(old: &[u8], new: &[u8], old_name: &str, new_name: &str) -> String {
+    match (std::str::from_utf8(old), std::str::from_utf8(new)) {
+        (Ok(old), Ok(new)) => TextDiff::from_lines(old, new)
+            .unified_diff()
+            .header(old_name, new_name)
+            .to_string(),
+        _ => format!("Binary files {} and {} differ\n", old_name, new_name),
+    }
+}
@@ -2,7 +2,7 @@ use std::{os::unix::process::CommandExt, process::Command};
 
 /// Asserts that input = expected with a pretty failure message
 #[cfg(test)]
-pub fn validate_processer_test(input: &Vec<String>, expected: &Vec<String>, out: &Vec<String>) {
+pub fn validate_processer_test(input: &[String], expected: &[String], out: &[String]) {
     assert_eq!(
         expected,
         out,
@@ -13,7 +13,7 @@ pub fn validate_processer_test(input: &Vec<String>, expected: &Vec<String>, out:
     );
 }
 
-pub fn to_string(args: &Vec<String>) -> String {
+pub fn to_string(args: &[String]) -> String {
     args.join(" ")
 }
 
@@ -25,17 +25,20 @@ pub fn from_string<S: AsRef<str>>(args: S) -> Vec<String> {
 }
 
 /// Check if args contain a specific flag
-pub fn contains_flag(args: &Vec<String>, flag: &str) -> bool {
+pub fn contains_flag(args: &[String], flag: &str) -> bool {
     args.iter().any(|arg| arg == flag)
 }
 
-/// Replaces the current process with a new one.
+/// Replaces the current process with a new one, adding env to its environment.
 /// Primarily used for executing shell expansions.
-pub fn execute_to_stdout(args: &Vec<String>) {
-    let _ = Command::new(&args[0]).args(&args[1..]).exec(); // This replaces the current process
+pub fn execute_to_stdout(args: &[String], env: &[(&str, String)]) {
+    let _ = Command::new(&args[0])
+        .args(&args[1..])
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .exec(); // This replaces the current process
 }
 
 /// Prints the finished command to stdout
-pub fn finish(args: &Vec<String>) {
+pub fn finish(args: &[String]) {
     println!("{}", args.join(" "));
 }
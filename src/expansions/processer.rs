@@ -1,55 +1,127 @@
-use std::path::Path;
-
 use clap::Args;
 
-use crate::{cli::Actionable, config::manager::ConfigFile};
+use crate::{
+    cli::Actionable,
+    config::manager::ConfigFile,
+    flake::{self, Location},
+    lock::{Input, LockFile},
+};
 
 use super::cmd;
 
-/// pkg -> nixpkgs#pkg
+/// pkg -> nixpkgs#pkg, with nixpkgs being any flake reference
 /// Avoids treating args as pkgs
-fn format_nixpkg(pkg: &str) -> String {
+fn format_nixpkg(pkg: &str, nixpkgs: &str) -> String {
     if pkg.starts_with('-') {
         pkg.to_string()
     } else {
-        format!("nixpkgs#{}", pkg)
+        format!("{}#{}", nixpkgs, pkg)
+    }
+}
+
+/// What pinned_nixpkgs produces followed by #pkg, so that reverse can turn it back into --locked
+pub const PINNED_NIXPKGS: &str = concat!(
+    r"(?i)^(?:(?:github|gitlab|sourcehut):[^/#]+/[^/#]*nixpkgs[^/#]*/[0-9a-f]{40}",
+    r"|git\+[^#]*nixpkgs[^#]*[?&]rev=[0-9a-f]{40}[^#]*",
+    r"|tarball\+[^#]+)#(.+)$"
+);
+
+/// The nixpkgs revision pinned by the flake.lock of the project, e.g.
+/// github:NixOS/nixpkgs/<rev>
+fn locked_nixpkgs() -> Option<String> {
+    let lock = LockFile::load(&flake::find()?.lock_file()).ok()?;
+    pinned_nixpkgs(lock.inputs().into_iter().find(|i| i.name == "nixpkgs")?)
+}
+
+/// A flake reference to exactly the revision an input is locked to. Works for forge, git and
+/// tarball inputs.
+fn pinned_nixpkgs(input: Input) -> Option<String> {
+    let source = input.source?;
+
+    match (input.kind.as_str(), input.rev) {
+        ("github" | "gitlab" | "sourcehut", Some(rev)) => {
+            Some(format!("{}:{}/{}", input.kind, source, rev))
+        }
+        ("git", Some(rev)) => {
+            let separator = if source.contains('?') { '&' } else { '?' };
+            Some(format!("git+{}{}rev={}", source, separator, rev))
+        }
+        // Locked tarball urls are already immutable
+        ("tarball", _) => Some(format!("tarball+{}", source)),
+        _ => None,
+    }
+}
+
+/// Where packages come from: the --nixpkgs flag, then with locked, or use_project_nixpkgs in the
+/// config, the nixpkgs that the project's flake.lock pins, then nixpkgs from the config.
+fn nixpkgs_ref(locked: bool, nixpkgs: Option<&str>) -> String {
+    if let Some(nixpkgs) = nixpkgs {
+        return nixpkgs.to_string();
     }
+
+    let config = ConfigFile::new().ok();
+    if locked || config.as_ref().is_some_and(|c| c.use_project_nixpkgs) {
+        match locked_nixpkgs() {
+            Some(nixpkgs) => return nixpkgs,
+            None if locked => eprintln!("No locked nixpkgs found, using the registry's nixpkgs"),
+            None => log::info!("No locked nixpkgs found, using the registry's nixpkgs"),
+        }
+    }
+
+    config.map_or_else(ConfigFile::default_nixpkgs, |c| c.nixpkgs)
 }
 
-/// Determines whether there is a nested flake in the CWD
-fn is_nested_flake() -> bool {
-    Path::new("./flake/flake.nix").is_file()
+/// Environment variable counting how many shells deep nf shell and nf develop are
+pub const SHELL_DEPTH: &str = "NF_SHELL_DEPTH";
+
+/// How many nix shells deep the current process is. Shells that weren't entered through nf
+/// count as one if nix develop marked them.
+pub fn shell_depth() -> usize {
+    match std::env::var(SHELL_DEPTH).ok().and_then(|d| d.parse().ok()) {
+        Some(depth) => depth,
+        None => usize::from(std::env::var_os("IN_NIX_SHELL").is_some()),
+    }
 }
 
 /// Determines whether already in nix shell or nix devshell
 fn is_in_shell() -> Result<bool, std::env::VarError> {
-    Ok(std::env::var("IN_NIX_SHELL")? == "impure".to_string())
+    Ok(std::env::var("IN_NIX_SHELL")? == "impure")
 }
 
 /// Functionality for both nix shell and develop
-fn process_shell_develop(args: &Vec<String>, shell: &String, is_develop: bool) -> Vec<String> {
+/// :name selects an output of the flake, e.g. nf develop :frontend -> nix develop .#frontend
+fn process_shell_develop(
+    args: &[String],
+    shell: &str,
+    nixpkgs: impl FnOnce() -> String,
+    is_develop: bool,
+) -> Vec<String> {
     let config = ConfigFile::new();
     let mut out = Vec::with_capacity(2);
 
     out.push("nix".to_string());
     out.push((if is_develop { "develop" } else { "shell" }).to_string());
 
-    if !args.is_empty() {
-        out.push(format_nixpkg(&args[0]));
+    if let Some(output) = args.first().and_then(|a| a.strip_prefix(':')) {
+        let reference = flake::find().map_or(".".to_string(), |f| f.reference());
+        out.push(format!("{}#{}", reference, output));
         out.extend_from_slice(&args[1..]);
-    } else if let Ok(c) = &config {
-        if c.nested_flakes && is_nested_flake() {
-            out.push("./flake".to_string());
-        }
+    } else if !args.is_empty() {
+        out.push(format_nixpkg(&args[0], &nixpkgs()));
+        out.extend_from_slice(&args[1..]);
+    } else if let Some(flake) = flake::find()
+        && flake.location != Location::Root
+    {
+        out.push(flake.reference());
     };
 
-    if !cmd::contains_flag(&args, "--command") {
+    if !cmd::contains_flag(args, "--command") {
         out.push("--command".to_string());
 
         let shell = if let Ok(c) = config {
             c.shell
         } else {
-            shell.clone()
+            shell.to_string()
         };
 
         out.push(shell);
@@ -62,6 +134,11 @@ pub trait Processer {
     /// Processes the shell expansion.
     fn process(&self) -> Vec<String>;
 
+    /// Whether the expansion enters a new shell
+    fn enters_shell(&self) -> bool {
+        false
+    }
+
     /// Processes and executes the shell expansion.
     /// If dryrun == true, then just println!() the expansion instead.
     fn execute(&self, dryrun: bool) {
@@ -80,7 +157,11 @@ pub trait Processer {
 
             cmd::finish(&cmd);
         } else {
-            cmd::execute_to_stdout(&cmd);
+            let env = match self.enters_shell() {
+                true => vec![(SHELL_DEPTH, (shell_depth() + 1).to_string())],
+                false => Vec::new(),
+            };
+            cmd::execute_to_stdout(&cmd, &env);
         }
     }
 }
@@ -91,6 +172,13 @@ pub struct Run {
     /// a delimiter.
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     args: Vec<String>,
+    /// Takes packages from the nixpkgs revision in the project's flake.lock instead of the
+    /// registry. nixpkgs can be locked to a github, gitlab, sourcehut, git or tarball input
+    #[arg(long, default_value_t = false)]
+    locked: bool,
+    /// Flake reference to take packages from instead of nixpkgs, e.g. a fork or a local checkout
+    #[arg(long, value_name = "FLAKE_REF", conflicts_with = "locked")]
+    nixpkgs: Option<String>,
 }
 
 impl Processer for Run {
@@ -103,7 +191,10 @@ impl Processer for Run {
         let mut out = Vec::new();
         out.extend(prefix);
 
-        out.push(format_nixpkg(&self.args[0]));
+        out.push(format_nixpkg(
+            &self.args[0],
+            &nixpkgs_ref(self.locked, self.nixpkgs.as_deref()),
+        ));
 
         if self.args.len() > 1 {
             if !cmd::contains_flag(&self.args, "--") {
@@ -133,11 +224,27 @@ pub struct Shell {
     /// If this is set to config, it will pull from the config file instead
     #[arg(long, default_value = "config")]
     shell: String,
+    /// Takes packages from the nixpkgs revision in the project's flake.lock instead of the
+    /// registry. nixpkgs can be locked to a github, gitlab, sourcehut, git or tarball input
+    #[arg(long, default_value_t = false)]
+    locked: bool,
+    /// Flake reference to take packages from instead of nixpkgs, e.g. a fork or a local checkout
+    #[arg(long, value_name = "FLAKE_REF", conflicts_with = "locked")]
+    nixpkgs: Option<String>,
 }
 
 impl Processer for Shell {
     fn process(&self) -> Vec<String> {
-        process_shell_develop(&self.args, &self.shell, false)
+        process_shell_develop(
+            &self.args,
+            &self.shell,
+            || nixpkgs_ref(self.locked, self.nixpkgs.as_deref()),
+            false,
+        )
+    }
+
+    fn enters_shell(&self) -> bool {
+        true
     }
 }
 
@@ -150,18 +257,34 @@ impl Actionable for Shell {
 #[derive(Debug, Args)]
 pub struct Develop {
     /// Arguments for the program. If you have arguments for the nix_cli, then place them before
-    /// a delimiter.
+    /// a delimiter. Start with :name to use the devShell called name of the project's flake.
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     args: Vec<String>,
 
     /// If this is set to config, it will pull from the config file instead
     #[arg(long, default_value = "config")]
     shell: String,
+    /// Takes packages from the nixpkgs revision in the project's flake.lock instead of the
+    /// registry. nixpkgs can be locked to a github, gitlab, sourcehut, git or tarball input
+    #[arg(long, default_value_t = false)]
+    locked: bool,
+    /// Flake reference to take packages from instead of nixpkgs, e.g. a fork or a local checkout
+    #[arg(long, value_name = "FLAKE_REF", conflicts_with = "locked")]
+    nixpkgs: Option<String>,
 }
 
 impl Processer for Develop {
     fn process(&self) -> Vec<String> {
-        process_shell_develop(&self.args, &self.shell, true)
+        process_shell_develop(
+            &self.args,
+            &self.shell,
+            || nixpkgs_ref(self.locked, self.nixpkgs.as_deref()),
+            true,
+        )
+    }
+
+    fn enters_shell(&self) -> bool {
+        true
     }
 }
 
@@ -175,12 +298,15 @@ impl Actionable for Develop {
 mod tests {
     use std::collections::HashMap;
 
+    use regex::Regex;
+
     use crate::{
         expansions::cmd::{self, validate_processer_test},
+        lock::Input,
         mode::Mode,
     };
 
-    use super::{Develop, Processer, Run, Shell};
+    use super::{Develop, PINNED_NIXPKGS, Processer, Run, Shell, pinned_nixpkgs};
 
     const SHELL: &str = "zsh";
 
@@ -193,6 +319,8 @@ mod tests {
             Mode::Run(_) => {
                 let p = Run {
                     args: input.clone(),
+                    locked: false,
+                    nixpkgs: None,
                 };
                 test_processer(input, expected, p);
             }
@@ -200,6 +328,8 @@ mod tests {
                 let p = Shell {
                     args: input.clone(),
                     shell: SHELL.to_string(),
+                    locked: false,
+                    nixpkgs: None,
                 };
                 test_processer(input, expected, p);
             }
@@ -207,6 +337,8 @@ mod tests {
                 let p = Develop {
                     args: input.clone(),
                     shell: SHELL.to_string(),
+                    locked: false,
+                    nixpkgs: None,
                 };
                 test_processer(input, expected, p);
             }
@@ -234,7 +366,14 @@ mod tests {
             ),
         ]);
 
-        test_processer_map(map, Mode::Run(Run { args: Vec::new() }));
+        test_processer_map(
+            map,
+            Mode::Run(Run {
+                args: Vec::new(),
+                locked: false,
+                nixpkgs: None,
+            }),
+        );
     }
 
     #[test]
@@ -252,6 +391,8 @@ mod tests {
             Mode::Shell(Shell {
                 args: Vec::new(),
                 shell: SHELL.to_string(),
+                locked: false,
+                nixpkgs: None,
             }),
         );
     }
@@ -271,7 +412,55 @@ mod tests {
             Mode::Develop(Develop {
                 args: Vec::new(),
                 shell: SHELL.to_string(),
+                locked: false,
+                nixpkgs: None,
             }),
         );
     }
+
+    #[test]
+    fn pinned() {
+        let rev = "0123456789abcdef0123456789abcdef01234567";
+        let input = |kind: &str, source: &str| Input {
+            name: "nixpkgs".to_string(),
+            kind: kind.to_string(),
+            source: Some(source.to_string()),
+            rev: Some(rev.to_string()),
+            last_modified: None,
+            follows: None,
+        };
+        let pattern = Regex::new(PINNED_NIXPKGS).unwrap();
+
+        for (kind, source, expected) in [
+            (
+                "github",
+                "NixOS/nixpkgs",
+                format!("github:NixOS/nixpkgs/{}", rev),
+            ),
+            ("gitlab", "me/nixpkgs", format!("gitlab:me/nixpkgs/{}", rev)),
+            (
+                "git",
+                "https://example.com/nixpkgs",
+                format!("git+https://example.com/nixpkgs?rev={}", rev),
+            ),
+            (
+                "git",
+                "https://example.com/nixpkgs?ref=main",
+                format!("git+https://example.com/nixpkgs?ref=main&rev={}", rev),
+            ),
+            (
+                "tarball",
+                "https://example.com/nixpkgs.tar.gz",
+                "tarball+https://example.com/nixpkgs.tar.gz".to_string(),
+            ),
+        ] {
+            let pinned = pinned_nixpkgs(input(kind, source)).unwrap();
+            assert_eq!(pinned, expected);
+
+            // reverse has to recognise everything --locked expands to
+            let reference = format!("{}#hello", pinned);
+            let captures = pattern.captures(&reference).unwrap();
+            assert_eq!(&captures[1], "hello");
+        }
+    }
 }
@@ -1,8 +1,9 @@
 use clap::Args;
+use regex::Regex;
 
-use crate::cli::Actionable;
+use crate::{cli::Actionable, config::manager::ConfigFile};
 
-use super::cmd;
+use super::{cmd, processer::PINNED_NIXPKGS};
 
 #[derive(Debug, Args)]
 pub struct UnProcesser {
@@ -12,9 +13,10 @@ pub struct UnProcesser {
 }
 
 impl Actionable for UnProcesser {
-    fn perform(&self, dryrun: bool) {
-        if dryrun {} // Gets rid of the warning since dryrun is not used here
-        let (expanded, shell) = self.unprocess();
+    fn perform(&self, _dryrun: bool) {
+        let nixpkgs =
+            ConfigFile::new().map_or_else(|_| ConfigFile::default_nixpkgs(), |c| c.nixpkgs);
+        let (expanded, shell) = self.unprocess(&nixpkgs);
 
         if let Some(s) = shell {
             println!(
@@ -28,10 +30,11 @@ impl Actionable for UnProcesser {
 
 impl UnProcesser {
     /// Reverses a shell expansion. For example: nix run nixpkgs#hello -> nf run hello
-    fn unprocess(&self) -> (Vec<String>, Option<String>) {
+    /// nixpkgs is the flake reference from the config, which is reversed like nixpkgs itself
+    fn unprocess(&self, nixpkgs: &str) -> (Vec<String>, Option<String>) {
         assert!(self.args.len() > 1, "There must be more than 2 arguments!");
 
-        let (args, shell) = self.get_args();
+        let (args, shell) = self.get_args(nixpkgs);
         let mut out = Vec::with_capacity(args.len() + 2);
 
         out.push("nf".to_string());
@@ -42,12 +45,17 @@ impl UnProcesser {
     }
 
     /// Unprocesses and returns everything after the first two arguments
-    fn get_args(&self) -> (Vec<String>, Option<String>) {
+    fn get_args(&self, nixpkgs: &str) -> (Vec<String>, Option<String>) {
+        let configured = format!("{}#", nixpkgs);
+
         let mut nix_args = Vec::new();
         let mut program_args = Vec::new();
 
         let mut pkg = None;
         let mut looking_for_pkg = true;
+        let mut locked = false;
+        // What --locked expands to
+        let pinned = Regex::new(PINNED_NIXPKGS).unwrap();
 
         let mut shell = None;
         let mut looking_for_shell = true;
@@ -71,12 +79,20 @@ impl UnProcesser {
                 to_program = true;
                 continue;
             }
-            if looking_for_pkg {
-                if let Some(p) = a.strip_prefix("nixpkgs#") {
-                    pkg = Some(p.to_string());
-                    looking_for_pkg = false;
-                    continue;
-                }
+            if looking_for_pkg
+                && let Some(p) = a
+                    .strip_prefix("nixpkgs#")
+                    .or_else(|| a.strip_prefix(&configured))
+            {
+                pkg = Some(p.to_string());
+                looking_for_pkg = false;
+                continue;
+            }
+            if looking_for_pkg && let Some(c) = pinned.captures(a) {
+                pkg = Some(c[1].to_string());
+                looking_for_pkg = false;
+                locked = true;
+                continue;
             }
 
             if to_program {
@@ -90,8 +106,12 @@ impl UnProcesser {
             nix_args.push("--".to_string());
         }
 
-        // Plus three for the potential package or shell args
-        let mut out = Vec::with_capacity(nix_args.len() + program_args.len() + 3);
+        // Plus four for the potential package, shell or --locked args
+        let mut out = Vec::with_capacity(nix_args.len() + program_args.len() + 4);
+
+        if locked {
+            out.push("--locked".to_string());
+        }
 
         if let Some(s) = shell.clone() {
             out.push("--shell".to_string());
@@ -120,7 +140,7 @@ mod tests {
         let up = UnProcesser {
             args: input.clone(),
         };
-        let out = up.unprocess().0;
+        let out = up.unprocess("nixpkgs").0;
         validate_processer_test(&input, &expected, &out);
     }
 
@@ -155,7 +175,7 @@ mod tests {
             let up = UnProcesser {
                 args: input.clone(),
             };
-            let out = up.get_args().0;
+            let out = up.get_args("nixpkgs").0;
             validate_processer_test(&input, &expected, &out);
         }
     }
@@ -171,11 +191,33 @@ mod tests {
                 "nix run to_nix nixpkgs#eza -- to_program",
                 "nf run eza to_nix -- to_program",
             ),
+            (
+                "nix run github:NixOS/nixpkgs/0123456789abcdef0123456789abcdef01234567#eza",
+                "nf run --locked eza",
+            ),
+            (
+                "nix run git+https://example.com/nixpkgs?rev=0123456789abcdef0123456789abcdef01234567#eza",
+                "nf run --locked eza",
+            ),
+            (
+                "nix run tarball+https://example.com/nixpkgs.tar.gz#eza",
+                "nf run --locked eza",
+            ),
         ]);
 
         test_unprocesser_map(map);
     }
 
+    #[test]
+    fn configured_nixpkgs() {
+        let input = cmd::from_string("nix run github:me/nixpkgs#eza -- to_program");
+        let up = UnProcesser {
+            args: input.clone(),
+        };
+        let out = up.unprocess("github:me/nixpkgs").0;
+        validate_processer_test(&input, &cmd::from_string("nf run eza to_program"), &out);
+    }
+
     #[test]
     fn nix_shell() {
         let map = HashMap::from([
@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{config::manager::ConfigFile, relocate::normalize};
+
+/// Where a flake was found, relative to the CWD
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Location {
+    /// ./flake.nix
+    Root,
+    /// In one of the flake search paths, e.g. ./flake/flake.nix
+    Nested,
+    /// Assigned to the CWD by the projects table of the config
+    Project,
+    /// In a parent directory, or one of its flake search paths
+    Parent,
+}
+
+impl Location {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Location::Root => "root",
+            Location::Nested => "nested",
+            Location::Project => "project",
+            Location::Parent => "parent",
+        }
+    }
+}
+
+/// The flake that nix commands run in the CWD would use
+#[derive(Debug)]
+pub struct Flake {
+    /// Directory containing flake.nix. Relative for root and nested flakes
+    pub dir: PathBuf,
+    pub location: Location,
+}
+
+impl Flake {
+    pub fn nix_file(&self) -> PathBuf {
+        self.dir.join("flake.nix")
+    }
+
+    pub fn lock_file(&self) -> PathBuf {
+        self.dir.join("flake.lock")
+    }
+
+    /// The directory as a flake reference for nix, e.g. ./flake
+    pub fn reference(&self) -> String {
+        if self.dir == Path::new(".") {
+            ".".to_string()
+        } else if self.dir.is_absolute() {
+            self.dir.display().to_string()
+        } else {
+            format!("./{}", self.dir.display())
+        }
+    }
+}
+
+/// Finds the first search path with a nested flake in the CWD
+fn find_nested(config: &ConfigFile) -> Option<PathBuf> {
+    config
+        .search_paths()
+        .into_iter()
+        .find(|p| p.join("flake.nix").is_file())
+}
+
+/// Expands a leading ~ to the home directory
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Finds the flake that the projects table of the config assigns to cwd. The project containing
+/// cwd most closely wins, and relative flake directories are resolved from the project.
+fn find_project(config: &ConfigFile, cwd: &Path) -> Option<PathBuf> {
+    config
+        .projects
+        .iter()
+        .map(|(project, flake)| (expand_home(Path::new(project)), flake))
+        .filter(|(project, _)| cwd.starts_with(project))
+        .max_by_key(|(project, _)| project.components().count())
+        .map(|(project, flake)| normalize(&project.join(expand_home(flake))))
+}
+
+/// Finds the flake for the CWD: a nested flake if nested_flakes is set in the config, then
+/// ./flake.nix, then the projects table of the config, then the nearest parent directory with a
+/// flake.nix of its own or in a search path
+pub fn find() -> Option<Flake> {
+    let config = ConfigFile::new().ok();
+    if let Some(dir) = config
+        .as_ref()
+        .filter(|c| c.nested_flakes)
+        .and_then(find_nested)
+    {
+        return Some(Flake {
+            dir,
+            location: Location::Nested,
+        });
+    }
+
+    if Path::new("flake.nix").is_file() {
+        return Some(Flake {
+            dir: PathBuf::from("."),
+            location: Location::Root,
+        });
+    }
+
+    let cwd = std::env::current_dir().ok()?;
+    if let Some(dir) = config.as_ref().and_then(|c| find_project(c, &cwd)) {
+        return Some(Flake {
+            dir,
+            location: Location::Project,
+        });
+    }
+
+    let search_paths = config.map(|c| c.search_paths()).unwrap_or_default();
+    cwd.ancestors()
+        .skip(1)
+        .flat_map(|d| {
+            std::iter::once(d.to_path_buf()).chain(search_paths.iter().map(|p| d.join(p)))
+        })
+        .find(|d| d.join("flake.nix").is_file())
+        .map(|dir| Flake {
+            dir,
+            location: Location::Parent,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{Flake, Location, find_project};
+    use crate::config::manager::ConfigFile;
+
+    #[test]
+    fn projects() {
+        let mut config: ConfigFile =
+            toml::from_str("shell = \"bash\"\nnested_flakes = true").unwrap();
+        config.projects = [
+            ("/repo".to_string(), PathBuf::from("flake")),
+            ("/repo/web".to_string(), PathBuf::from("../nix/web")),
+        ]
+        .into();
+
+        let find = |cwd: &str| find_project(&config, Path::new(cwd));
+        assert_eq!(find("/repo/web/src"), Some(PathBuf::from("/repo/nix/web")));
+        assert_eq!(find("/repo/api"), Some(PathBuf::from("/repo/flake")));
+        assert_eq!(find("/other"), None);
+    }
+
+    #[test]
+    fn references() {
+        let flake = |dir: &str, location| Flake {
+            dir: PathBuf::from(dir),
+            location,
+        };
+
+        // nf develop :frontend -> nix develop <reference>#frontend
+        assert_eq!(flake(".", Location::Root).reference(), ".");
+        assert_eq!(flake("flake", Location::Nested).reference(), "./flake");
+        assert_eq!(
+            flake("/repo/nix/web", Location::Project).reference(),
+            "/repo/nix/web"
+        );
+    }
+}
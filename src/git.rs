@@ -0,0 +1,261 @@
+use std::{
+    fs,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{config::manager::ConfigFile, prompt};
+
+/// Runs git in dir
+pub fn run(dir: &Path, args: &[&str]) -> Result<()> {
+    let status = command(dir).args(args).status()?;
+    if !status.success() {
+        bail!("`git {}` failed with {}", args.join(" "), status);
+    }
+
+    Ok(())
+}
+
+/// Reads a file as it was at a revision. The path is relative to dir.
+pub fn show(dir: &Path, rev: &str, path: &Path) -> Result<String> {
+    let output = command(dir)
+        .arg("show")
+        .arg(format!("{}:./{}", rev, path.display()))
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "Couldn't read {} at {}: {}",
+            path.display(),
+            rev,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Three-way merges the changes from base to theirs into ours with git merge-file. Returns the
+/// merged contents, or None if they conflict.
+pub fn merge_file(ours: &[u8], base: &[u8], theirs: &[u8]) -> Result<Option<Vec<u8>>> {
+    let dir = std::env::temp_dir().join(format!("nf-merge-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let files = ["ours", "base", "theirs"].map(|name| dir.join(name));
+    for (file, contents) in files.iter().zip([ours, base, theirs]) {
+        fs::write(file, contents)?;
+    }
+
+    let output = Command::new("git")
+        .args(["merge-file", "--stdout"])
+        .args(&files)
+        .output();
+    let _ = fs::remove_dir_all(&dir);
+    let output = output?;
+
+    // The exit code is the number of conflicts, or negative on errors
+    match output.status.code() {
+        Some(0) => Ok(Some(output.stdout)),
+        Some(1..=127) => Ok(None),
+        _ => bail!(
+            "`git merge-file` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+    }
+}
+
+/// git with its working directory set to dir. An empty dir stands for the CWD
+fn command(dir: &Path) -> Command {
+    let mut git = Command::new("git");
+    if !dir.as_os_str().is_empty() {
+        git.current_dir(dir);
+    }
+    git
+}
+
+/// Runs git in dir and returns what it printed
+fn output(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = command(dir).args(args).output()?;
+    if !output.status.success() {
+        bail!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// The top directory of the work tree that dir is in
+fn toplevel(dir: &Path) -> Result<PathBuf> {
+    Ok(PathBuf::from(
+        output(dir, &["rev-parse", "--show-toplevel"])?.trim_end(),
+    ))
+}
+
+/// Whether git is installed and dir is inside a work tree
+pub fn is_work_tree(dir: &Path) -> bool {
+    command(dir)
+        .args(["rev-parse", "--is-inside-work-tree"])
+        .stderr(Stdio::null())
+        .output()
+        .is_ok_and(|o| o.status.success() && o.stdout.starts_with(b"true"))
+}
+
+/// Whether git tracks the file, or anything inside the directory, at path relative to dir
+pub fn is_tracked(dir: &Path, path: &Path) -> bool {
+    command(dir)
+        .args(["ls-files", "--error-unmatch", "--"])
+        .arg(path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Prints a git command as dryrun does, or runs it
+fn run_or_show(dir: &Path, args: &[&str], dryrun: bool) -> Result<()> {
+    if dryrun {
+        println!("run     git {}", args.join(" "));
+        Ok(())
+    } else {
+        run(dir, args)
+    }
+}
+
+/// Moves tracked paths in the index after they were moved on disk, like git mv would. Only
+/// the renamed entries change, so unstaged edits and untracked files stay out of the index.
+pub fn record_moves(dir: &Path, moves: &[(PathBuf, PathBuf)], dryrun: bool) -> Result<()> {
+    for (from, to) in moves {
+        if dryrun {
+            println!("index   {} -> {}", from.display(), to.display());
+        } else {
+            move_index(dir, from, to)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Renames the index entries at or inside from to to, keeping their staged contents. Both
+/// paths are relative to dir, which can be anywhere in the work tree.
+pub fn move_index(dir: &Path, from: &Path, to: &Path) -> Result<()> {
+    // update-index --index-info reads paths relative to the top of the work tree, so both
+    // commands run there
+    let prefix = PathBuf::from(output(dir, &["rev-parse", "--show-prefix"])?.trim_end());
+    let (from, to) = (prefix.join(from), prefix.join(to));
+    let top = toplevel(dir)?;
+
+    let output = command(&top)
+        .args(["ls-files", "--stage", "-z", "--"])
+        .arg(&from)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "Couldn't list the index entries of {}: {}",
+            from.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    // Entries look like "<mode> <object> <stage>\t<path>". Mode 0 removes a path
+    let mut info = String::new();
+    for entry in String::from_utf8(output.stdout)?.split_terminator('\0') {
+        let (meta, path) = entry
+            .split_once('\t')
+            .context("Unexpected output from git ls-files")?;
+        let mut fields = meta.split(' ');
+        let (Some(mode), Some(object)) = (fields.next(), fields.next()) else {
+            bail!("Unexpected output from git ls-files: {}", entry);
+        };
+
+        let rest = Path::new(path).strip_prefix(&from)?;
+        let moved = match rest.as_os_str().is_empty() {
+            true => to.to_path_buf(),
+            false => to.join(rest),
+        };
+        info.push_str(&format!("0 {}\t{}\n", "0".repeat(object.len()), path));
+        info.push_str(&format!("{} {}\t{}\n", mode, object, moved.display()));
+    }
+
+    let mut child = command(&top)
+        .args(["update-index", "--index-info"])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .context("Couldn't write to git update-index")?
+        .write_all(info.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("`git update-index --index-info` failed with {}", status);
+    }
+
+    Ok(())
+}
+
+/// Marks new files with git add --intent-to-add so that flakes can see them. Asks first,
+/// unless git_intent_to_add is set in the config.
+pub fn offer_intent_to_add(dir: &Path, paths: &[PathBuf], dryrun: bool) -> Result<()> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    let mut args = vec!["add", "--intent-to-add", "--"];
+    let names: Vec<String> = paths
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    args.extend(names.iter().map(String::as_str));
+
+    let automatic = ConfigFile::new().is_ok_and(|c| c.git_intent_to_add);
+    if dryrun || automatic {
+        return run_or_show(dir, &args, dryrun);
+    }
+
+    println!("Flakes only see files that git knows about. New files:");
+    names.iter().for_each(|n| println!("  {}", n));
+
+    if std::io::stdin().is_terminal() && prompt::confirm("Add them with --intent-to-add?")? {
+        run(dir, &args)
+    } else {
+        println!("Skipped. Set git_intent_to_add in the config to do this automatically.");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{move_index, output, run};
+
+    #[test]
+    fn move_index_in_subdirectory() {
+        let repo = std::env::temp_dir().join(format!("nf-git-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&repo);
+        fs::create_dir_all(repo.join("backend")).unwrap();
+        fs::write(repo.join("backend/flake.nix"), "{ }").unwrap();
+        run(&repo, &["init", "--quiet"]).unwrap();
+        run(&repo, &["add", "--", "backend/flake.nix"]).unwrap();
+
+        let backend = repo.join("backend");
+        fs::create_dir(backend.join("flake")).unwrap();
+        fs::rename(backend.join("flake.nix"), backend.join("flake/flake.nix")).unwrap();
+        move_index(
+            &backend,
+            Path::new("flake.nix"),
+            Path::new("flake/flake.nix"),
+        )
+        .unwrap();
+
+        let files = output(&repo, &["ls-files"]).unwrap();
+        assert_eq!(files, "backend/flake/flake.nix\n");
+
+        fs::remove_dir_all(repo).unwrap();
+    }
+}
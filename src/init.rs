@@ -1,64 +1,376 @@
-use crate::{cli::Actionable, config::manager::map_templates};
-use anyhow::Result;
-use clap::Args;
-use std::{collections::HashMap, fs, path::PathBuf, process::exit};
+use crate::{
+    cli::{Actionable, fail},
+    config::manager::ConfigFile,
+    diff, git,
+    journal::{self, Step},
+    nest::{self, FLAKE_FILES},
+    plan::{self, FsPlan},
+    prompt, relocate,
+    templates::{
+        self, Operations, Template, detect,
+        placeholders::{self, Variables},
+        record::{RECORD_FILE, Record},
+    },
+};
+use anyhow::{Result, bail};
+use clap::{Args, ValueEnum};
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::IsTerminal,
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
-/// Recursively gets the full path of every file in a path
-fn recursive_read_dir(base: &PathBuf) -> Result<Vec<PathBuf>> {
-    let mut out = Vec::new();
+/// Return any entries about to be copied to root if they are already present
+fn obstructed_inits(root: &Path, operations: &Operations) -> Vec<PathBuf> {
+    operations
+        .iter()
+        .map(|(_, to)| root.join(to))
+        .filter(|to| to.exists())
+        .collect()
+}
 
-    for entry in fs::read_dir(base)? {
-        let entry = entry?;
-        let path = entry.path();
+/// Collects every placeholder used in the template's file names and text file contents
+fn template_placeholders(operations: &Operations) -> Result<BTreeSet<String>> {
+    let mut out = BTreeSet::new();
 
-        if path.is_dir() {
-            out.extend(recursive_read_dir(&path)?);
-        }
+    for (from, to) in operations {
+        out.extend(placeholders::find(&to.to_string_lossy()));
 
-        out.push(path);
+        if fs::symlink_metadata(from)?.is_file()
+            && let Ok(contents) = String::from_utf8(fs::read(from)?)
+        {
+            out.extend(placeholders::find(&contents));
+        }
     }
 
     Ok(out)
 }
 
-/// Returns a HashMap of paths "from -> to" for initializing a template
-fn map_operations(template: &PathBuf) -> Result<HashMap<PathBuf, PathBuf>> {
-    let mut out = HashMap::new();
+/// Fills in the placeholders of every destination path
+fn render_paths(operations: Operations, variables: &Variables) -> Result<Operations> {
+    operations
+        .into_iter()
+        .map(|(from, to)| {
+            let to = placeholders::render(&to.to_string_lossy(), variables)?;
+            Ok((from, PathBuf::from(to)))
+        })
+        .collect()
+}
+
+/// Where init puts the flake files when neither --nested nor --flat is passed
+fn default_nest_dir() -> Option<PathBuf> {
+    ConfigFile::new()
+        .is_ok_and(|c| c.nested_flakes)
+        .then(nest::nest_dir)
+}
+
+/// The paths "from -> to" that init would create from a template, relative to the project, with
+/// the default values filled in. Placeholders without one are left as they are.
+pub fn preview_operations(template: &Template) -> Result<Operations> {
+    let operations = template.map_operations()?;
 
-    for full in recursive_read_dir(&template)? {
-        let relative = full.strip_prefix(template)?;
-        // if relative.is_file() {
-        out.insert(full.clone(), relative.to_path_buf());
-        // }
+    let mut variables = placeholders::builtin_defaults(Path::new(""));
+    variables.extend(template.manifest.defaults());
+    for (_, to) in &operations {
+        for name in placeholders::find(&to.to_string_lossy()) {
+            let raw = format!("{{{{{}}}}}", name);
+            variables.entry(name).or_insert(raw);
+        }
     }
 
-    Ok(out)
+    let operations = render_paths(operations, &variables)?;
+    Ok(place_flake(operations, default_nest_dir().as_deref()))
+}
+
+/// Moves the template's flake files into nest, or into the root if there is none
+fn place_flake(operations: Operations, nest: Option<&Path>) -> Operations {
+    let layout = Path::new(templates::NEST_DIR);
+    let mut out: Operations = operations
+        .into_iter()
+        .map(|(from, to)| {
+            match FLAKE_FILES
+                .iter()
+                .find(|f| to == Path::new(f) || to == layout.join(f))
+            {
+                Some(file) => (from, nest.unwrap_or(Path::new("")).join(file)),
+                None => (from, to),
+            }
+        })
+        .collect();
+
+    // An emptied nest dir is left out
+    if !out
+        .iter()
+        .any(|(_, to)| to.starts_with(layout) && to != layout)
+    {
+        out.retain(|(_, to)| to != layout);
+    }
+
+    out.sort_by(|a, b| a.1.cmp(&b.1));
+    out
+}
+
+/// The files that placement moved, from where the template has them to where init puts them
+fn moved_files(before: &Operations, after: &Operations) -> Vec<(PathBuf, PathBuf)> {
+    before
+        .iter()
+        .filter_map(|(from, old)| {
+            let (_, new) = after.iter().find(|(f, _)| f == from)?;
+            (new != old).then(|| (old.clone(), new.clone()))
+        })
+        .collect()
+}
+
+/// Rewrites the relative paths of a file that placement moved, like nest does, so that it keeps
+/// finding the files around it
+fn relocate_file(text: &str, from: &Path, to: &Path, moved: &[(PathBuf, PathBuf)]) -> String {
+    let dir = |p: &Path| p.parent().unwrap_or(Path::new("")).to_path_buf();
+    let rewrite = relocate::rewrite(text, &dir(from), &dir(to), |p| {
+        moved
+            .iter()
+            .find(|(old, _)| old == p)
+            .map_or_else(|| p.to_path_buf(), |(_, new)| new.clone())
+    });
+
+    for warning in &rewrite.warnings {
+        log::warn!("{}: {}", to.display(), warning);
+        println!("Warning: {}: {}", to.display(), warning);
+    }
+
+    rewrite.text
+}
+
+/// How to handle a template file that conflicts with an existing file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Conflict {
+    /// Keep the existing file
+    Ours,
+    /// Replace the existing file with the template's
+    Theirs,
+    /// Keep the existing file and write the template's next to it as <file>.nf-new
+    Sidecar,
 }
 
-/// Return any entries about to be copied to CWD if they are already present
-fn obstructed_inits(operations: &HashMap<PathBuf, PathBuf>) -> Result<Vec<&PathBuf>> {
-    let mut out = Vec::new();
+/// What plan_template does with an existing file that differs from the template's
+enum Resolution {
+    Keep,
+    Replace,
+    Sidecar,
+    /// Writes the project's and the template's changes merged together
+    Merge(Vec<u8>),
+}
 
-    for to in operations.values() {
-        if to.exists() {
-            out.push(to);
+impl From<Conflict> for Resolution {
+    fn from(conflict: Conflict) -> Self {
+        match conflict {
+            Conflict::Ours => Resolution::Keep,
+            Conflict::Theirs => Resolution::Replace,
+            Conflict::Sidecar => Resolution::Sidecar,
         }
     }
+}
 
-    Ok(out)
+/// Path that a conflicting template file is written to with Conflict::Sidecar
+fn sidecar(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".nf-new");
+    path.with_file_name(name)
 }
 
-/// Initializes the template, overwriting anything in its way
-fn initialize_template(operations: &HashMap<PathBuf, PathBuf>) -> Result<()> {
-    for (from, to) in operations.iter() {
-        if from.is_dir() && !to.is_dir() {
-            fs::create_dir_all(to)?;
+/// Shows the diff of a conflicting file and asks how to resolve it
+fn ask_conflict(to: &Path, existing: &[u8], new: &[u8]) -> Result<Conflict> {
+    let name = to.to_string_lossy();
+    print!(
+        "{}",
+        diff::unified(existing, new, &name, &format!("{} (template)", name))
+    );
+
+    loop {
+        let answer = prompt::ask(
+            &format!(
+                "{} already exists. [k]eep it, [r]eplace it or [s]ave the template's as {}?",
+                name,
+                sidecar(to).display()
+            ),
+            None,
+        )?;
+
+        match answer.to_lowercase().as_str() {
+            "k" | "keep" => return Ok(Conflict::Ours),
+            "r" | "replace" => return Ok(Conflict::Theirs),
+            "s" | "save" | "sidecar" => return Ok(Conflict::Sidecar),
+            _ => continue,
         }
     }
+}
+
+/// Returns the contents of a template file with its placeholders filled in, or None if it has
+/// no placeholders and can be copied as is
+fn render_file(from: &Path, variables: &Variables) -> Result<Option<Vec<u8>>> {
+    match String::from_utf8(fs::read(from)?) {
+        Ok(contents) if !placeholders::find(&contents).is_empty() => Ok(Some(
+            placeholders::render(&contents, variables)?.into_bytes(),
+        )),
+        _ => Ok(None),
+    }
+}
+
+/// Reads a file, or the target of a symlink
+fn read_entry(path: &Path) -> Result<Vec<u8>> {
+    if fs::symlink_metadata(path)?.is_symlink() {
+        Ok(fs::read_link(path)?.into_os_string().into_vec())
+    } else {
+        Ok(fs::read(path)?)
+    }
+}
+
+/// Plans the initialization of the template into root, recording the hash of every file it
+/// writes. Files in moved get their relative paths rewritten for their new location. Existing
+/// files that differ from the template's are handled by resolve, which is given the path along
+/// with the existing and new contents. The plan is journaled as command.
+fn plan_template<F>(
+    command: String,
+    root: &Path,
+    operations: &Operations,
+    moved: &[(PathBuf, PathBuf)],
+    record: &mut Record,
+    mut resolve: F,
+) -> Result<FsPlan>
+where
+    F: FnMut(&Path, &[u8], &[u8]) -> Result<Resolution>,
+{
+    let mut plan = FsPlan::journaled(command);
+    let mut planned = BTreeSet::new();
+    if !root.as_os_str().is_empty() && !root.is_dir() {
+        plan.mkdir(root);
+        planned.insert(root.to_path_buf());
+    }
 
-    for (from, to) in operations.iter() {
-        if from.is_file() {
-            fs::copy(from, to)?;
+    for (from, relative) in operations {
+        let to = &root.join(relative);
+        let metadata = fs::symlink_metadata(from)?;
+        if metadata.is_dir() {
+            if !to.is_dir() {
+                plan.mkdir(to);
+                planned.insert(to.clone());
+            }
+            continue;
+        }
+
+        if let Some(parent) = to.parent()
+            && !parent.as_os_str().is_empty()
+            && !parent.is_dir()
+            && planned.insert(parent.to_path_buf())
+        {
+            plan.mkdir(parent);
+        }
+
+        let mut rendered = match metadata.is_symlink() {
+            true => None,
+            false => render_file(from, &record.variables)?,
+        };
+        let mut new = match &rendered {
+            Some(r) => r.clone(),
+            None => read_entry(from)?,
+        };
+
+        if let Some((old, _)) = moved.iter().find(|(_, placed)| placed == relative)
+            && !metadata.is_symlink()
+            && let Ok(text) = std::str::from_utf8(&new)
+        {
+            let text = relocate_file(text, old, relative, moved);
+            if text.as_bytes() != new {
+                new = text.into_bytes();
+                rendered = Some(new.clone());
+            }
+        }
+        record.insert(relative, &new);
+        let mut to = to.clone();
+
+        if to.is_file() || to.is_symlink() {
+            let existing = read_entry(&to)?;
+            if existing == new {
+                log::info!("{} is already up to date", to.display());
+                continue;
+            }
+
+            match resolve(&to, &existing, &new)? {
+                Resolution::Keep => {
+                    log::info!("Keeping {}", to.display());
+                    continue;
+                }
+                Resolution::Replace => {}
+                Resolution::Sidecar => to = sidecar(&to),
+                Resolution::Merge(merged) => {
+                    plan.write_as(to, merged, metadata.permissions());
+                    continue;
+                }
+            }
+        }
+
+        match rendered {
+            Some(r) => plan.write_as(to, r, metadata.permissions()),
+            None => plan.copy(from, to),
+        }
+    }
+
+    plan.write(root.join(RECORD_FILE), record.to_toml()?.into_bytes());
+    Ok(plan)
+}
+
+/// Skips files deleted from the project since the last init or update, returning them, and
+/// points out those that were dropped from the template
+fn flag_update(record: &Record, root: &Path, operations: &mut Operations) -> Vec<PathBuf> {
+    for file in record.files.keys() {
+        if !operations.iter().any(|(_, to)| to == Path::new(file)) {
+            println!("{} is no longer part of the template", file);
+        }
+    }
+
+    let mut deleted = Vec::new();
+    operations.retain(|(from, to)| {
+        if !from.is_dir() && !plan::exists(&root.join(to)) && record.contains(to) {
+            println!(
+                "{} was deleted since the template was applied, skipping it",
+                to.display()
+            );
+            deleted.push(to.clone());
+            return false;
+        }
+        true
+    });
+
+    deleted
+}
+
+/// Merges the template's changes since it was last applied into a file the project modified,
+/// if there is a base to merge from and the changes don't conflict
+fn merge_update(record: &Record, relative: &Path, existing: &[u8], new: &[u8]) -> Option<Vec<u8>> {
+    let base = record.base(relative)?;
+    match git::merge_file(existing, base.as_bytes(), new) {
+        Ok(merged) => merged,
+        Err(e) => {
+            log::warn!("{:?}", e);
+            None
+        }
+    }
+}
+
+/// Runs commands in dir, stopping at the first one that fails
+fn run_commands(dir: &Path, commands: &[String]) -> Result<()> {
+    for command in commands {
+        log::info!("Running: {}", command);
+        let mut sh = Command::new("sh");
+        if !dir.as_os_str().is_empty() {
+            sh.current_dir(dir);
+        }
+        let status = sh.arg("-c").arg(command).status()?;
+
+        if !status.success() {
+            bail!("`{}` failed with {}", command, status);
         }
     }
 
@@ -67,39 +379,406 @@ fn initialize_template(operations: &HashMap<PathBuf, PathBuf>) -> Result<()> {
 
 #[derive(Debug, Args)]
 pub struct Init {
-    /// Name of the template file in <config_dir>/templates/
-    template: String,
+    /// Name of the template file in <config_dir>/templates/. Detected from the project's files
+    /// if not given, falling back to basic
+    template: Option<String>,
+
+    /// Directory to initialize the template in, created if needed. Defaults to the CWD
+    dir: Option<PathBuf>,
+
+    /// Merges changes to the template into the project it was initialized with. Files the
+    /// project hasn't modified are updated, modified ones get a three-way merge, and those that
+    /// conflict are flagged
+    #[arg(long, default_value_t = false, conflicts_with = "force")]
+    update: bool,
+
+    /// Puts the flake files in the nest dir, whatever the template's layout. Defaults to
+    /// nested_flakes in the config
+    #[arg(long, default_value_t = false, conflicts_with = "flat")]
+    nested: bool,
+
+    /// Puts the flake files in the root, whatever the template's layout
+    #[arg(long, default_value_t = false)]
+    flat: bool,
 
     /// Whether to overwrite files in the CWD with those pulled by the template
     #[arg(long, default_value_t = false)]
     force: bool,
+
+    /// Shows a diff for every file that already exists and asks whether to keep it, replace it
+    /// or write the template's version next to it
+    #[arg(long, default_value_t = false, conflicts_with = "force")]
+    merge: bool,
+
+    /// Resolves every file that already exists the same way, without asking
+    #[arg(long, value_enum, conflicts_with_all = ["force", "merge"])]
+    conflict: Option<Conflict>,
+
+    /// Sets the value of a template {{placeholder}}, e.g. --set project_name=hello
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = placeholders::parse_assignment)]
+    set: Vec<(String, String)>,
+
+    /// Prompts for every placeholder in the template, including those with defaults
+    #[arg(short, long, default_value_t = false)]
+    interactive: bool,
+
+    /// Runs the post-init hooks of the template's manifest without asking for confirmation
+    #[arg(long, default_value_t = false)]
+    run_hooks: bool,
 }
 
-impl Actionable for Init {
-    fn perform(&self, dryrun: bool) {
-        let templates = map_templates().expect("Couldn't map templates!");
-        let template = templates.get(&self.template).expect("Template not found!");
+impl Init {
+    /// Directory the template is initialized in. Empty for the CWD, so paths stay relative
+    fn root(&self) -> &Path {
+        self.dir.as_deref().unwrap_or(Path::new(""))
+    }
+
+    /// Runs, or in dryrun shows, the post-init hooks after asking for confirmation if needed
+    fn hooks(&self, hooks: &[String], dryrun: bool) {
+        if hooks.is_empty() {
+            return;
+        }
+
+        if dryrun {
+            hooks.iter().for_each(|h| println!("run     {}", h));
+            return;
+        }
+
+        println!("The template has post-init hooks:");
+        hooks.iter().for_each(|h| println!("  {}", h));
+
+        let confirmed = self.run_hooks
+            || (std::io::stdin().is_terminal()
+                && prompt::confirm("Run them?").expect("Couldn't read confirmation!"));
+
+        if confirmed {
+            run_commands(self.root(), hooks).expect("Failed to run post-init hooks!");
+        } else {
+            println!("Skipped the hooks. Pass --run-hooks to run them.");
+        }
+    }
+
+    /// The nest dir if the flake files go in it. Updates keep the layout of the project
+    fn nest_dir(&self, record: Option<&Record>) -> Option<PathBuf> {
+        let nest = nest::nest_dir();
+        let nested = match record {
+            _ if self.nested || self.flat => self.nested,
+            Some(record) => record.contains(&nest.join("flake.nix")),
+            None => return default_nest_dir(),
+        };
+
+        nested.then_some(nest)
+    }
+
+    /// Initializes or updates the template, returning the record of the files it wrote. The
+    /// changes are journaled as command, init <template> by default.
+    fn apply(&self, command: Option<String>, dryrun: bool) -> Record {
+        let root = self.root();
+        let record = if self.update {
+            match Record::load(root).expect("Couldn't read the template record!") {
+                Some(record) => Some(record),
+                None => fail(&format!(
+                    "There is no {}, so there is nothing to update.",
+                    root.join(RECORD_FILE).display()
+                )),
+            }
+        } else {
+            None
+        };
 
-        let operations = map_operations(&template).expect("Couldn't map template initialization!");
+        let name = match (&self.template, &record) {
+            (Some(name), _) => name.clone(),
+            (None, Some(record)) => record.template.clone(),
+            (None, None) => {
+                let mapping = ConfigFile::new().map(|c| c.detect).unwrap_or_default();
+                let templates = Template::all().expect("Couldn't load templates!");
+                let detection = detect::detect(root, &mapping, &templates);
 
-        let obstructions = obstructed_inits(&operations).expect("Couldn't handle obstructions!");
-        obstructions
+                println!("Using {}: {}", detection.template, detection.reason);
+                detection.template
+            }
+        };
+
+        let template = Template::find(&name).expect("Couldn't load template!");
+        let manifest = &template.manifest;
+
+        let mut operations = template
+            .map_operations()
+            .expect("Couldn't map template initialization!");
+
+        let mut needed = template_placeholders(&operations).expect("Couldn't read template files!");
+        manifest
+            .hooks
             .iter()
-            .for_each(|o| log::warn!("{:?} already exists!", o));
+            .for_each(|h| needed.extend(placeholders::find(h)));
+
+        let mut defaults = placeholders::builtin_defaults(root);
+        defaults.extend(manifest.defaults());
+
+        // Updates reuse the values the project was initialized with
+        let mut set: Vec<(String, String)> =
+            record.iter().flat_map(|r| r.variables.clone()).collect();
+        set.extend(self.set.iter().cloned());
 
-        if !self.force && !obstructions.is_empty() {
-            let msg = "The template couldn't be initialized because some files already exist. Pass --force to initialize anyway, overwriting conflicting files.";
-            log::error!("{}", msg);
-            eprintln!("{}", msg);
-            exit(1);
+        let variables = placeholders::resolve(
+            &needed,
+            &set,
+            &defaults,
+            &manifest.descriptions(),
+            self.interactive,
+        )
+        .expect("Couldn't fill in template placeholders!");
+        if let Err(e) = manifest.validate(&variables) {
+            fail(&e.to_string());
         }
+        operations =
+            render_paths(operations, &variables).expect("Couldn't fill in template file names!");
+        let layout = operations.clone();
+        operations = place_flake(operations, self.nest_dir(record.as_ref()).as_deref());
+        let moved = moved_files(&layout, &operations);
 
-        log::debug!("Templates: {:?}", templates);
+        let conflict = if self.force {
+            Some(Conflict::Theirs)
+        } else {
+            self.conflict
+        };
+
+        let mut written = Record::new(&name, &variables);
+        if let Some(record) = &record {
+            // Deleted files stay recorded, so that later updates don't bring them back
+            for path in flag_update(record, root, &mut operations) {
+                written.keep(record, &path);
+            }
+        } else {
+            let obstructions = obstructed_inits(root, &operations);
+            obstructions
+                .iter()
+                .for_each(|o| log::warn!("{:?} already exists!", o));
+
+            if conflict.is_none() && !self.merge && !obstructions.is_empty() {
+                fail(
+                    "The template couldn't be initialized because some files already exist. Pass --force to initialize anyway, overwriting conflicting files, or --merge/--conflict to choose what to keep.",
+                );
+            }
+        }
+
+        log::debug!("Template: {:?}", template);
+        log::debug!("Variables: {:?}", variables);
         log::debug!("Operations: {:?}", operations);
-        log::debug!("obstructions: {:?}", obstructions);
-        if !dryrun {
-            // cp -r $temeplate/* ./
-            initialize_template(&operations).unwrap();
+
+        let ask = |to: &Path, existing: &[u8], new: &[u8]| match conflict {
+            Some(c) => Ok(c),
+            None if self.merge || record.is_none() => ask_conflict(to, existing, new),
+            None => Ok(Conflict::Sidecar),
+        };
+
+        // Files the project hasn't touched since the last init or update take the template's
+        // changes, and files the template hasn't changed are left alone. Files both changed are
+        // merged, and those that conflict are flagged and resolved like a conflict.
+        let resolve = |to: &Path, existing: &[u8], new: &[u8]| -> Result<Resolution> {
+            let Some(r) = &record else {
+                return Ok(ask(to, existing, new)?.into());
+            };
+
+            let relative = to.strip_prefix(root)?;
+            if r.is_unmodified(relative, existing) {
+                Ok(Resolution::Replace)
+            } else if r.is_unmodified(relative, new) {
+                Ok(Resolution::Keep)
+            } else if let Some(merged) = merge_update(r, relative, existing, new) {
+                println!("Merged the template's changes into {}", to.display());
+                Ok(Resolution::Merge(merged))
+            } else {
+                println!(
+                    "{} was modified since the template was applied and couldn't be merged",
+                    to.display()
+                );
+                Ok(ask(to, existing, new)?.into())
+            }
+        };
+
+        // cp -r $temeplate/* ./
+        let command = command.unwrap_or_else(|| format!("init {}", name));
+        plan_template(command, root, &operations, &moved, &mut written, resolve)
+            .expect("Couldn't plan template initialization!")
+            .run(dryrun)
+            .expect("Failed to initialize template!");
+
+        if record.is_some() {
+            return written;
         }
+
+        let hooks: Vec<String> = manifest
+            .hooks
+            .iter()
+            .map(|h| placeholders::render(h, &variables))
+            .collect::<Result<_>>()
+            .expect("Couldn't fill in hook placeholders!");
+        self.hooks(&hooks, dryrun);
+        written
+    }
+}
+
+impl Actionable for Init {
+    fn perform(&self, dryrun: bool) {
+        let record = self.apply(None, dryrun);
+
+        let root = self.root();
+        if git::is_work_tree(root) {
+            let untracked: Vec<PathBuf> = record
+                .files
+                .keys()
+                .map(PathBuf::from)
+                .filter(|p| !git::is_tracked(root, p))
+                .collect();
+            git::offer_intent_to_add(root, &untracked, dryrun)
+                .expect("Couldn't add the new files to git!");
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct New {
+    /// Directory of the new project. Its name is used as the project_name
+    name: PathBuf,
+
+    /// Name of the template in <config_dir>/templates/
+    #[arg(short, long, default_value = "basic")]
+    template: String,
+
+    /// Sets the value of a template {{placeholder}}, e.g. --set description=hello
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = placeholders::parse_assignment)]
+    set: Vec<(String, String)>,
+
+    /// Prompts for every placeholder in the template, including those with defaults
+    #[arg(short, long, default_value_t = false)]
+    interactive: bool,
+
+    /// Runs the post-init hooks of the template's manifest without asking for confirmation
+    #[arg(long, default_value_t = false)]
+    run_hooks: bool,
+}
+
+impl Actionable for New {
+    fn perform(&self, dryrun: bool) {
+        if self.name.exists() {
+            fail(&format!("{} already exists.", self.name.display()));
+        }
+
+        let project_name = self
+            .name
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| fail("The project needs a name."));
+
+        let mut set = vec![("project_name".to_string(), project_name)];
+        set.extend(self.set.iter().cloned());
+
+        let init = Init {
+            template: Some(self.template.clone()),
+            dir: Some(self.name.clone()),
+            update: false,
+            nested: false,
+            flat: false,
+            force: false,
+            merge: false,
+            conflict: None,
+            set,
+            interactive: self.interactive,
+            run_hooks: self.run_hooks,
+        };
+        let record = init.apply(Some(format!("new {}", self.name.display())), dryrun);
+
+        // Flakes only see files git knows about
+        let mut add = vec!["add", "--"];
+        add.extend(record.files.keys().map(String::as_str));
+        add.push(RECORD_FILE);
+        let commands = [vec!["init", "--quiet"], add];
+
+        if dryrun {
+            commands
+                .iter()
+                .for_each(|c| println!("run     git {}", c.join(" ")));
+            return;
+        }
+
+        for args in commands {
+            git::run(&self.name, &args).expect("Couldn't set up the git repository!");
+        }
+
+        // So that undo can remove the project directory again
+        let repository = std::path::absolute(self.name.join(".git"))
+            .map_err(anyhow::Error::from)
+            .and_then(|path| journal::append(Step::Generated { path }));
+        if let Err(e) = repository {
+            eprintln!("Warning: undo won't remove the git repository: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{place_flake, relocate_file};
+
+    #[test]
+    fn placement() {
+        let nested = vec![
+            (PathBuf::from("t/flake"), PathBuf::from("flake")),
+            (
+                PathBuf::from("t/flake/flake.nix"),
+                PathBuf::from("flake/flake.nix"),
+            ),
+            (PathBuf::from("t/README.md"), PathBuf::from("README.md")),
+        ];
+        let destinations = |operations: Vec<(PathBuf, PathBuf)>| -> Vec<PathBuf> {
+            operations.into_iter().map(|(_, to)| to).collect()
+        };
+
+        let nest = Some(Path::new("flake"));
+        let flat = place_flake(nested.clone(), None);
+        assert_eq!(
+            destinations(flat.clone()),
+            vec![PathBuf::from("README.md"), PathBuf::from("flake.nix")]
+        );
+        assert_eq!(
+            destinations(place_flake(flat.clone(), Some(Path::new(".nix")))),
+            vec![PathBuf::from(".nix/flake.nix"), PathBuf::from("README.md")]
+        );
+        assert_eq!(
+            destinations(place_flake(flat, nest)),
+            vec![PathBuf::from("README.md"), PathBuf::from("flake/flake.nix")]
+        );
+        assert_eq!(
+            destinations(place_flake(nested, nest)),
+            vec![
+                PathBuf::from("README.md"),
+                PathBuf::from("flake"),
+                PathBuf::from("flake/flake.nix")
+            ]
+        );
+    }
+
+    #[test]
+    fn relocation() {
+        let moved = vec![
+            (PathBuf::from("flake.nix"), PathBuf::from("flake/flake.nix")),
+            (
+                PathBuf::from("flake.lock"),
+                PathBuf::from("flake/flake.lock"),
+            ),
+        ];
+        let text = "src = ./.; lock = ./Cargo.lock; flake = ./flake.lock;";
+
+        assert_eq!(
+            relocate_file(
+                text,
+                Path::new("flake.nix"),
+                Path::new("flake/flake.nix"),
+                &moved
+            ),
+            "src = ./..; lock = ../Cargo.lock; flake = ./flake.lock;"
+        );
     }
 }
@@ -0,0 +1,310 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::manager::ConfigFile,
+    git,
+    plan::{exists, move_path, remove_path},
+    templates::record::hash,
+    time,
+};
+
+const ENTRY_FILE: &str = "entry.toml";
+
+/// Where the undo journal is kept. This lives outside of the config dir so that
+/// `nf config destroy` can be undone as well.
+pub fn get_journal_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .expect("Couldn't get local data directory!")
+        .join("nf")
+        .join("journal")
+}
+
+/// Reverses a single applied filesystem operation
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Step {
+    /// A file or directory that didn't exist before
+    Created {
+        path: PathBuf,
+        /// Hash of a created file's contents, so that later edits aren't thrown away
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hash: Option<String>,
+    },
+    /// A directory made by another program, e.g. git init, removed along with its contents
+    Generated { path: PathBuf },
+    /// A path that was renamed from -> to
+    Moved { from: PathBuf, to: PathBuf },
+    /// A path that was overwritten or removed, with its original kept at backup
+    Restore {
+        path: PathBuf,
+        backup: PathBuf,
+        /// Hash of the contents that overwrote it, so that later edits aren't thrown away
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hash: Option<String>,
+    },
+    /// Index entries of the git repository at repository that followed a move, relative to it
+    IndexMoved {
+        repository: PathBuf,
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Created { path, .. } => write!(f, "remove  {}", path.display()),
+            Step::Generated { path } => {
+                write!(f, "remove  {} and everything in it", path.display())
+            }
+            Step::Moved { from, to } => {
+                write!(f, "move    {} -> {}", to.display(), from.display())
+            }
+            Step::Restore { path, .. } => write!(f, "restore {}", path.display()),
+            Step::IndexMoved {
+                repository,
+                from,
+                to,
+            } => write!(
+                f,
+                "index   {} -> {}",
+                repository.join(to).display(),
+                repository.join(from).display()
+            ),
+        }
+    }
+}
+
+/// Hash of a regular file's contents. Directories and symlinks have none
+pub fn file_hash(path: &Path) -> Option<String> {
+    if !fs::symlink_metadata(path).ok()?.is_file() {
+        return None;
+    }
+
+    fs::read(path).ok().map(|contents| hash(&contents))
+}
+
+impl Step {
+    /// The path of a created or overwritten file that was edited after nf wrote it
+    pub fn modified(&self) -> Option<&Path> {
+        match self {
+            Step::Created {
+                path,
+                hash: Some(hash),
+            }
+            | Step::Restore {
+                path,
+                hash: Some(hash),
+                ..
+            } if file_hash(path).is_some_and(|h| &h != hash) => Some(path),
+            _ => None,
+        }
+    }
+
+    fn revert(&self) -> Result<()> {
+        match self {
+            Step::Created { path, .. } => {
+                if fs::symlink_metadata(path)?.is_dir() {
+                    fs::remove_dir(path)
+                } else {
+                    fs::remove_file(path)
+                }
+                .with_context(|| format!("Couldn't remove {}", path.display()))?;
+            }
+            Step::Generated { path } => fs::remove_dir_all(path)
+                .with_context(|| format!("Couldn't remove {}", path.display()))?,
+            Step::Moved { from, to } => fs::rename(to, from)
+                .with_context(|| format!("Couldn't move {} back", to.display()))?,
+            Step::Restore { path, backup, .. } => {
+                if exists(path) {
+                    remove_path(path)?;
+                }
+                move_path(backup, path)
+                    .with_context(|| format!("Couldn't restore {}", path.display()))?;
+            }
+            Step::IndexMoved {
+                repository,
+                from,
+                to,
+            } => git::move_index(repository, to, from)
+                .with_context(|| format!("Couldn't move {} back in git", to.display()))?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Reverts steps newest first. Steps that were reverted are removed from the list, so on error
+/// it only contains what is left to undo.
+pub fn revert(steps: &mut Vec<Step>) -> Result<()> {
+    while let Some(step) = steps.pop() {
+        log::debug!("Reverting: {}", step);
+        if let Err(e) = step.revert() {
+            steps.push(step);
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// A single undoable command in the journal
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// The nf command that made the changes, e.g. "init rust"
+    pub command: String,
+    /// Unix timestamp of when the changes were made
+    pub created: u64,
+    /// Directory the command was run from
+    pub cwd: PathBuf,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+impl Entry {
+    /// Creates a new, empty entry on disk
+    pub fn create(command: &str) -> Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let dir = get_journal_dir().join(format!("{:024}", nanos));
+
+        fs::create_dir_all(&dir).context("Couldn't create undo journal entry")?;
+
+        Ok(Self {
+            command: command.to_string(),
+            created: time::now(),
+            cwd: std::env::current_dir()?,
+            steps: Vec::new(),
+            dir,
+        })
+    }
+
+    fn load(dir: PathBuf) -> Result<Self> {
+        let contents = fs::read_to_string(dir.join(ENTRY_FILE))?;
+        let mut entry: Entry = toml::from_str(&contents)?;
+        entry.dir = dir;
+        Ok(entry)
+    }
+
+    /// Where overwritten and removed files are kept
+    pub fn backup_dir(&self) -> PathBuf {
+        self.dir.join("backups")
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::write(self.dir.join(ENTRY_FILE), toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Deletes the entry along with its backups
+    pub fn discard(self) {
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            log::warn!("Couldn't remove journal entry {:?}: {}", self.dir, e);
+        }
+    }
+}
+
+/// Loads every journal entry, newest first
+pub fn entries() -> Result<Vec<Entry>> {
+    let dir = get_journal_dir();
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+
+    let mut out = Vec::with_capacity(dirs.len());
+    for dir in dirs.into_iter().rev() {
+        match Entry::load(dir.clone()) {
+            Ok(entry) => out.push(entry),
+            Err(e) => log::warn!("Skipping broken journal entry {:?}: {}", dir, e),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Adds a step to the newest entry, for changes made outside of a plan right after it was
+/// applied
+pub fn append(step: Step) -> Result<()> {
+    let mut entry = entries()?
+        .into_iter()
+        .next()
+        .context("The undo journal is empty")?;
+    entry.steps.push(step);
+    entry.save()
+}
+
+/// Removes the oldest entries beyond the configured undo_limit
+pub fn prune() -> Result<()> {
+    let limit = ConfigFile::new()
+        .map(|c| c.undo_limit)
+        .unwrap_or_else(|_| ConfigFile::default_undo_limit());
+
+    for entry in entries()?.into_iter().skip(limit) {
+        log::debug!("Pruning journal entry: {}", entry.command);
+        entry.discard();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, Step};
+
+    #[test]
+    fn roundtrip() {
+        let entry = Entry {
+            command: "nest".to_string(),
+            created: 1,
+            cwd: "/project".into(),
+            steps: vec![
+                Step::Created {
+                    path: "/project/flake".into(),
+                    hash: None,
+                },
+                Step::Moved {
+                    from: "/project/flake.nix".into(),
+                    to: "/project/flake/flake.nix".into(),
+                },
+                Step::Restore {
+                    path: "/project/README.md".into(),
+                    backup: "/journal/0".into(),
+                    hash: Some("cd".to_string()),
+                },
+                Step::Created {
+                    path: "/project/flake/flake.nix".into(),
+                    hash: Some("ab".to_string()),
+                },
+            ],
+            dir: Default::default(),
+        };
+
+        let parsed: Entry = toml::from_str(&toml::to_string(&entry).unwrap()).unwrap();
+        assert_eq!(parsed.command, entry.command);
+        assert_eq!(parsed.steps.len(), 4);
+        assert!(matches!(parsed.steps[1], Step::Moved { .. }));
+        assert!(matches!(&parsed.steps[2], Step::Restore { hash: Some(h), .. } if h == "cd"));
+        assert!(matches!(&parsed.steps[3], Step::Created { hash: Some(h), .. } if h == "ab"));
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::{Actionable, fail, print_json},
+    flake, git, time,
+};
+
+/// An input of a node, either the name of another node or a path of inputs that it follows
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Reference {
+    Node(String),
+    Follows(Vec<String>),
+}
+
+/// Where an input was locked to
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Locked {
+    #[serde(rename = "type")]
+    kind: String,
+    owner: Option<String>,
+    repo: Option<String>,
+    url: Option<String>,
+    path: Option<String>,
+    rev: Option<String>,
+    last_modified: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Node {
+    #[serde(default)]
+    inputs: BTreeMap<String, Reference>,
+    locked: Option<Locked>,
+}
+
+/// A parsed flake.lock
+#[derive(Debug, Deserialize)]
+pub struct LockFile {
+    nodes: BTreeMap<String, Node>,
+    root: String,
+}
+
+/// A direct input of the flake, as locked
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Input {
+    pub name: String,
+    /// github, git, path, tarball...
+    pub kind: String,
+    /// owner/repo for forges, otherwise the url or path
+    pub source: Option<String>,
+    pub rev: Option<String>,
+    /// Unix timestamp of the locked revision
+    pub last_modified: Option<u64>,
+    /// The input path this one follows, e.g. nixpkgs, instead of being locked itself
+    pub follows: Option<String>,
+}
+
+impl Input {
+    /// Seconds since the locked revision was made
+    pub fn age(&self) -> Option<u64> {
+        self.last_modified.map(|m| time::now().saturating_sub(m))
+    }
+
+    /// The first 7 characters of the revision, like git shows them
+    pub fn short_rev(&self) -> Option<&str> {
+        self.rev.as_deref().map(|r| &r[..r.len().min(7)])
+    }
+
+    /// What the input is locked to, e.g. NixOS/nixpkgs abcdef0 (2023-11-14)
+    fn describe(&self) -> String {
+        match &self.follows {
+            Some(follows) => format!("follows {}", follows),
+            None => format!(
+                "{} {}{}",
+                self.source.as_deref().unwrap_or(&self.kind),
+                self.short_rev().unwrap_or("-"),
+                self.last_modified
+                    .map(|m| format!(" ({})", time::date(m)))
+                    .unwrap_or_default()
+            ),
+        }
+    }
+
+    /// Prints the input as a row of the inputs table
+    fn print_row(&self) {
+        match &self.follows {
+            Some(follows) => println!("{:<16}follows {}", self.name, follows),
+            None => println!(
+                "{:<16}{:<10}{:<32}{:<9}{}",
+                self.name,
+                self.kind,
+                self.source.as_deref().unwrap_or("-"),
+                self.short_rev().unwrap_or("-"),
+                self.age().map(time::human_age).unwrap_or("-".to_string())
+            ),
+        }
+    }
+}
+
+impl LockFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents).with_context(|| format!("Couldn't parse {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        Ok(serde_json::from_str(contents)?)
+    }
+
+    /// The direct inputs of the flake, sorted by name
+    pub fn inputs(&self) -> Vec<Input> {
+        let mut out = Vec::new();
+        self.collect(&self.root, "", false, &mut out);
+        out
+    }
+
+    /// Every input, including those of inputs, named by their path, e.g. utils/nixpkgs
+    pub fn all_inputs(&self) -> Vec<Input> {
+        let mut out = Vec::new();
+        self.collect(&self.root, "", true, &mut out);
+        out
+    }
+
+    fn collect(&self, node: &str, prefix: &str, recursive: bool, out: &mut Vec<Input>) {
+        let Some(node) = self.nodes.get(node) else {
+            return;
+        };
+
+        for (name, reference) in &node.inputs {
+            let name = format!("{}{}", prefix, name);
+            let (locked, follows) = match reference {
+                Reference::Node(node) => {
+                    (self.nodes.get(node).and_then(|n| n.locked.clone()), None)
+                }
+                Reference::Follows(path) => (None, Some(path.join("/"))),
+            };
+            let locked = locked.unwrap_or_default();
+
+            let source = match (&locked.owner, &locked.repo) {
+                (Some(owner), Some(repo)) => Some(format!("{}/{}", owner, repo)),
+                _ => locked.url.clone().or(locked.path.clone()),
+            };
+
+            out.push(Input {
+                name: name.clone(),
+                kind: locked.kind,
+                source,
+                rev: locked.rev,
+                last_modified: locked.last_modified,
+                follows,
+            });
+
+            // Lock files are trees, the depth check only guards against malformed ones
+            if recursive
+                && let Reference::Node(child) = reference
+                && name.matches('/').count() < 32
+            {
+                self.collect(child, &format!("{}/", name), true, out);
+            }
+        }
+    }
+}
+
+/// A changed input, as locked before and after
+#[derive(Debug, Serialize)]
+struct Change {
+    name: String,
+    old: Input,
+    new: Input,
+}
+
+/// Differences between two versions of a lock file
+#[derive(Debug, Default, Serialize)]
+struct Changes {
+    added: Vec<Input>,
+    removed: Vec<Input>,
+    changed: Vec<Change>,
+}
+
+/// Compares every input of two lock files by name
+fn compare(old: Vec<Input>, new: Vec<Input>) -> Changes {
+    let mut old: BTreeMap<String, Input> = old.into_iter().map(|i| (i.name.clone(), i)).collect();
+    let mut out = Changes::default();
+
+    for input in new {
+        match old.remove(&input.name) {
+            None => out.added.push(input),
+            Some(previous) if previous != input => out.changed.push(Change {
+                name: input.name.clone(),
+                old: previous,
+                new: input,
+            }),
+            Some(_) => {}
+        }
+    }
+    out.removed = old.into_values().collect();
+
+    out
+}
+
+/// Finds the flake.lock of the flake for the CWD, the same way status does
+fn find_lock() -> PathBuf {
+    let lock = flake::find().map(|f| f.lock_file());
+    match lock {
+        Some(lock) if lock.is_file() => lock,
+        _ => fail("There is no flake.lock for the CWD. Run `nix flake lock` first."),
+    }
+}
+
+#[derive(Debug, Args)]
+struct Inputs {
+    /// Prints the inputs as json
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+impl Actionable for Inputs {
+    fn perform(&self, _dryrun: bool) {
+        let path = find_lock();
+        let inputs = LockFile::load(&path)
+            .expect("Couldn't read flake.lock!")
+            .inputs();
+
+        if self.json {
+            print_json(&inputs);
+            return;
+        }
+
+        println!("{}", path.display());
+        inputs.iter().for_each(Input::print_row);
+    }
+}
+
+#[derive(Debug, Args)]
+struct Outdated {
+    /// Inputs locked to a revision older than this are outdated, e.g. 12h, 30d, 2w or 1y
+    #[arg(long, default_value = "30d", value_parser = time::parse_age)]
+    older_than: u64,
+
+    /// Prints the outdated inputs as json
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+impl Actionable for Outdated {
+    /// Exits with 1 if any input is outdated, so it can be used in CI
+    fn perform(&self, _dryrun: bool) {
+        let inputs = LockFile::load(&find_lock())
+            .expect("Couldn't read flake.lock!")
+            .inputs();
+        let outdated: Vec<&Input> = inputs
+            .iter()
+            .filter(|i| i.age().is_some_and(|age| age > self.older_than))
+            .collect();
+
+        if self.json {
+            print_json(&outdated);
+        } else if outdated.is_empty() {
+            println!(
+                "Every input was locked within the last {}.",
+                time::human_age(self.older_than)
+            );
+        } else {
+            outdated.iter().for_each(|i| i.print_row());
+        }
+
+        if !outdated.is_empty() {
+            exit(1);
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct Diff {
+    /// Git revision to compare the working flake.lock against
+    #[arg(default_value = "HEAD")]
+    rev: String,
+
+    /// Prints the changes as json
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+impl Actionable for Diff {
+    fn perform(&self, _dryrun: bool) {
+        let path = find_lock();
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        let old = match git::show(dir, &self.rev, Path::new("flake.lock")) {
+            Ok(contents) => LockFile::parse(&contents).expect("Couldn't parse the old flake.lock!"),
+            Err(e) => fail(&e.to_string()),
+        };
+        let new = LockFile::load(&path).expect("Couldn't read flake.lock!");
+        let changes = compare(old.all_inputs(), new.all_inputs());
+
+        if self.json {
+            print_json(&changes);
+            return;
+        }
+
+        if changes.added.is_empty() && changes.removed.is_empty() && changes.changed.is_empty() {
+            println!("No inputs changed since {}.", self.rev);
+            return;
+        }
+
+        for input in &changes.added {
+            println!("+ {:<24}{}", input.name, input.describe());
+        }
+        for input in &changes.removed {
+            println!("- {:<24}{}", input.name, input.describe());
+        }
+        for change in &changes.changed {
+            println!(
+                "~ {:<24}{} -> {}",
+                change.name,
+                change.old.describe(),
+                change.new.describe()
+            );
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Action {
+    /// Lists the inputs of the flake with the revision and age they are locked to
+    Inputs(Inputs),
+    /// Lists inputs locked to old revisions, exiting with 1 if there are any
+    Outdated(Outdated),
+    /// Shows which inputs, including those of inputs, changed since a git revision
+    Diff(Diff),
+}
+
+impl Actionable for Action {
+    fn perform(&self, dryrun: bool) {
+        match self {
+            Action::Inputs(inputs) => inputs.perform(dryrun),
+            Action::Outdated(outdated) => outdated.perform(dryrun),
+            Action::Diff(diff) => diff.perform(dryrun),
+        };
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct Lock {
+    /// Lock action to perform
+    #[command(subcommand)]
+    action: Action,
+}
+
+impl Actionable for Lock {
+    fn perform(&self, dryrun: bool) {
+        self.action.perform(dryrun);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LockFile, compare};
+
+    const LOCK: &str = r#"{
+      "nodes": {
+        "nixpkgs": {
+          "locked": {
+            "lastModified": 1700000000,
+            "narHash": "sha256-x",
+            "owner": "NixOS",
+            "repo": "nixpkgs",
+            "rev": "abcdef0123456789",
+            "type": "github"
+          },
+          "original": { "owner": "NixOS", "repo": "nixpkgs", "type": "github" }
+        },
+        "utils": {
+          "inputs": { "nixpkgs": ["nixpkgs"] },
+          "locked": { "lastModified": 1600000000, "type": "git", "url": "https://example.com/utils" }
+        },
+        "root": {
+          "inputs": { "nixpkgs": "nixpkgs", "utils": "utils", "pinned": ["utils", "nixpkgs"] }
+        }
+      },
+      "root": "root",
+      "version": 7
+    }"#;
+
+    #[test]
+    fn inputs() {
+        let inputs = LockFile::parse(LOCK).unwrap().inputs();
+        assert_eq!(inputs.len(), 3);
+
+        assert_eq!(inputs[0].name, "nixpkgs");
+        assert_eq!(inputs[0].source.as_deref(), Some("NixOS/nixpkgs"));
+        assert_eq!(inputs[0].last_modified, Some(1700000000));
+
+        assert_eq!(inputs[1].follows.as_deref(), Some("utils/nixpkgs"));
+        assert_eq!(inputs[2].kind, "git");
+        assert_eq!(
+            inputs[2].source.as_deref(),
+            Some("https://example.com/utils")
+        );
+    }
+
+    #[test]
+    fn transitive() {
+        let lock = LockFile::parse(LOCK).unwrap();
+        let names: Vec<String> = lock.all_inputs().into_iter().map(|i| i.name).collect();
+        assert_eq!(names, vec!["nixpkgs", "pinned", "utils", "utils/nixpkgs"]);
+
+        let mut new = lock.all_inputs();
+        new[0].rev = Some("0123456".to_string());
+        new.remove(1);
+        let changes = compare(lock.all_inputs(), new);
+
+        assert!(changes.added.is_empty());
+        assert_eq!(changes.removed[0].name, "pinned");
+        assert_eq!(changes.changed[0].name, "nixpkgs");
+    }
+}
@@ -3,9 +3,21 @@ mod expansions;
 
 mod cli;
 mod completions;
+mod diff;
+mod flake;
+mod git;
 mod init;
+mod journal;
+mod lock;
 mod mode;
 mod nest;
+mod plan;
+mod prompt;
+mod relocate;
+mod status;
+mod templates;
+mod time;
+mod undo;
 
 use clap::Parser;
 use cli::Args;
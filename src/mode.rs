@@ -2,7 +2,7 @@ use crate::{
     cli::Actionable,
     config,
     expansions::{processer, unprocesser},
-    init, nest,
+    init, lock, nest, status, templates, undo,
 };
 use clap::Subcommand;
 
@@ -18,12 +18,22 @@ pub enum Mode {
     Reverse(unprocesser::UnProcesser),
     /// Copies flake templates from ~/.config/nf/templates/<name> to ./flake.nix
     Init(init::Init),
+    /// Creates a project directory from a template and sets up a git repository for it
+    New(init::New),
     /// Manages the config, usually found in ~/.config/nf
     Config(config::command::Config),
+    /// Lists and inspects the templates in ~/.config/nf/templates
+    Templates(templates::command::Templates),
     /// Moves ./flake.* -> ./flake/flake.*. Useful to keep CWD out of the Nix store.
     Nest(nest::Nest),
     /// Moves ./flake/flake.* -> ./flake.*. Useful to put CWD back into the Nix store.
     Unnest(nest::UnNest),
+    /// Inspects the flake.lock of the flake for the CWD
+    Lock(lock::Lock),
+    /// Shows where the active flake is, how old its lock is and whether you are in a nix shell
+    Status(status::Status),
+    /// Reverts the last filesystem changing command (init, nest, unnest, config add/remove/destroy)
+    Undo(undo::Undo),
 }
 
 impl Actionable for Mode {
@@ -34,9 +44,14 @@ impl Actionable for Mode {
             Mode::Develop(develop) => develop.perform(dryrun),
             Mode::Reverse(reverse) => reverse.perform(dryrun),
             Mode::Init(init) => init.perform(dryrun),
+            Mode::New(new) => new.perform(dryrun),
             Mode::Config(config) => config.perform(dryrun),
+            Mode::Templates(templates) => templates.perform(dryrun),
             Mode::Nest(nest) => nest.perform(dryrun),
             Mode::Unnest(unnest) => unnest.perform(dryrun),
+            Mode::Lock(lock) => lock.perform(dryrun),
+            Mode::Status(status) => status.perform(dryrun),
+            Mode::Undo(undo) => undo.perform(dryrun),
         };
     }
 }
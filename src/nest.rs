@@ -1,69 +1,315 @@
-use std::{fs, path::Path};
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
 
+use anyhow::Result;
 use clap::Args;
 
-use crate::cli::Actionable;
+use crate::{
+    cli::{Actionable, fail},
+    config::manager::ConfigFile,
+    diff, git,
+    journal::{self, Step},
+    plan::{self, FsPlan},
+    relocate,
+};
 
-#[derive(Debug, Args)]
-pub struct Nest;
+/// Files that are moved when nesting and unnesting
+pub const FLAKE_FILES: [&str; 2] = ["flake.nix", "flake.lock"];
 
-impl Actionable for Nest {
-    fn perform(&self, dryrun: bool) {
-        let destination = Path::new("flake");
-        let flake_from = Path::new("flake.nix");
-        let lock_from = Path::new("flake.lock");
-        let flake_to = destination.join(flake_from);
-        let lock_to = destination.join(lock_from);
+/// Directory that nested flakes live in, from the config
+pub fn nest_dir() -> PathBuf {
+    ConfigFile::new()
+        .map(|c| c.nest_dir)
+        .unwrap_or_else(|_| ConfigFile::default_nest_dir())
+}
 
-        log::debug!("Creating dir: {}", destination.display());
-        if !dryrun {
-            fs::create_dir("flake").expect("Failed to create ./flake");
+/// Paths that move along with the flake: flake.nix, flake.lock, the configured companions and
+/// those passed with --with, relative to the project root
+fn moved_paths(with: &[PathBuf]) -> Vec<PathBuf> {
+    let companions = ConfigFile::new()
+        .map(|c| c.nest_companions)
+        .unwrap_or_default();
+
+    let mut out: Vec<PathBuf> = FLAKE_FILES.iter().map(PathBuf::from).collect();
+    for path in companions.iter().chain(with) {
+        if path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+            fail(&format!(
+                "{} must be a path inside the project",
+                path.display()
+            ));
+        }
+        if !out.contains(path) {
+            out.push(path.clone());
+        }
+    }
+
+    out
+}
+
+/// Fails with every problem found, if there are any
+fn refuse(problems: Vec<String>, action: &str) {
+    if !problems.is_empty() {
+        fail(&format!(
+            "Refusing to {}, nothing was changed:\n  {}",
+            action,
+            problems.join("\n  ")
+        ));
+    }
+}
+
+/// Problems that would make moving the paths from one root to the other fail halfway
+fn check_moves(paths: &[&PathBuf], to: &Path) -> Vec<String> {
+    paths
+        .iter()
+        .map(|p| to.join(p))
+        .filter(|p| plan::exists(p))
+        .map(|p| format!("{} already exists", p.display()))
+        .collect()
+}
+
+/// Plans moving each path from one root to the other, creating missing parents
+fn plan_moves(plan: &mut FsPlan, paths: &[&PathBuf], from: &Path, to: &Path) {
+    for path in paths {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+            && !to.join(parent).is_dir()
+        {
+            plan.mkdir(to.join(parent));
+        }
+
+        plan.rename(from.join(path), to.join(path));
+    }
+}
+
+/// Plans rewriting the relative paths of flake.nix for its new location, from one directory of
+/// the project to another. In dryrun the changes are shown as a diff.
+fn plan_rewrite<F>(plan: &mut FsPlan, from: &Path, to: &Path, relocate: F, dryrun: bool)
+where
+    F: Fn(&Path) -> PathBuf,
+{
+    let (old, new) = (from.join("flake.nix"), to.join("flake.nix"));
+    let Ok(text) = fs::read_to_string(&old) else {
+        return;
+    };
+
+    let rewrite = relocate::rewrite(&text, from, to, relocate);
+    for warning in &rewrite.warnings {
+        log::warn!("{}", warning);
+        println!("Warning: {}", warning);
+    }
+
+    if rewrite.text == text {
+        return;
+    }
+
+    if dryrun {
+        print!(
+            "{}",
+            diff::unified(
+                text.as_bytes(),
+                rewrite.text.as_bytes(),
+                &old.to_string_lossy(),
+                &new.to_string_lossy()
+            )
+        );
+    }
+    plan.write(new, rewrite.text.into_bytes());
+}
+
+/// Moved paths split by whether git tracks them, so the index can follow the move
+#[derive(Default)]
+struct GitMoves {
+    tracked: Vec<(PathBuf, PathBuf)>,
+    /// Destinations of the paths that git didn't know about
+    untracked: Vec<PathBuf>,
+}
+
+impl GitMoves {
+    /// Looks up the paths before they are moved. Outside of a git work tree there is nothing
+    /// to do.
+    fn new(paths: &[&PathBuf], from: &Path, to: &Path) -> Self {
+        let mut out = Self::default();
+        if !git::is_work_tree(Path::new("")) {
+            return out;
         }
 
-        if flake_from.exists() {
-            log::debug!("{} -> {}", flake_from.display(), flake_to.display());
-            if !dryrun {
-                fs::rename(flake_from, flake_to).expect("Failed to move flake.nix");
+        for path in paths {
+            if git::is_tracked(Path::new(""), &from.join(path)) {
+                out.tracked.push((from.join(path), to.join(path)));
+            } else {
+                out.untracked.push(to.join(path));
             }
         }
 
-        if lock_from.exists() {
-            log::debug!("{} -> {}", lock_from.display(), lock_to.display());
-            if !dryrun {
-                fs::rename(lock_from, lock_to).expect("Failed to move flake.lock");
+        out
+    }
+
+    fn apply(&self, dryrun: bool) {
+        git::record_moves(Path::new(""), &self.tracked, dryrun)
+            .expect("Couldn't move the files in git!");
+        if !dryrun {
+            self.journal();
+        }
+        git::offer_intent_to_add(Path::new(""), &self.untracked, dryrun)
+            .expect("Couldn't add the moved files to git!");
+    }
+
+    /// Lets undo move the index entries back along with the files
+    fn journal(&self) {
+        let Ok(repository) = std::env::current_dir() else {
+            return;
+        };
+
+        for (from, to) in &self.tracked {
+            let step = Step::IndexMoved {
+                repository: repository.clone(),
+                from: from.clone(),
+                to: to.clone(),
+            };
+            if let Err(e) = journal::append(step) {
+                eprintln!(
+                    "Warning: undo won't move {} back in git: {:?}",
+                    to.display(),
+                    e
+                );
             }
         }
     }
 }
 
+/// Entries of dir, relative to it, that aren't any of the moved paths or inside one of them
+fn leftovers(dir: &Path, relative: &Path, moved: &[&PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut out = Vec::new();
+
+    for entry in fs::read_dir(dir.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+
+        if moved.contains(&&path) {
+            continue;
+        } else if entry.file_type()?.is_dir() && moved.iter().any(|m| m.starts_with(&path)) {
+            out.extend(leftovers(dir, &path, moved)?);
+        } else {
+            out.push(path);
+        }
+    }
+
+    Ok(out)
+}
+
 #[derive(Debug, Args)]
-pub struct UnNest;
+pub struct Nest {
+    /// Extra file or directory to move along with the flake, on top of nest_companions in the
+    /// config. Can be given multiple times
+    #[arg(long = "with", value_name = "PATH")]
+    with: Vec<PathBuf>,
+}
 
-impl Actionable for UnNest {
+impl Actionable for Nest {
     fn perform(&self, dryrun: bool) {
-        let source = Path::new("flake");
-        let flake_to = Path::new("flake.nix");
-        let lock_to = Path::new("flake.lock");
-        let flake_from = source.join(flake_to);
-        let lock_from = source.join(lock_to);
-
-        if flake_to.exists() {
-            log::debug!("{} -> {}", flake_to.display(), flake_from.display());
-            if !dryrun {
-                fs::rename(flake_to, flake_from).expect("Failed to move flake.nix");
-            }
+        let destination = &nest_dir();
+        let paths = moved_paths(&self.with);
+
+        let present: Vec<&PathBuf> = paths.iter().filter(|p| plan::exists(p)).collect();
+
+        let mut problems: Vec<String> = self
+            .with
+            .iter()
+            .filter(|p| !plan::exists(p))
+            .map(|p| format!("{} doesn't exist", p.display()))
+            .collect();
+        if !Path::new("flake.nix").is_file() {
+            problems.push("There is no flake.nix in the CWD".to_string());
         }
+        if plan::exists(destination) && !destination.is_dir() {
+            problems.push(format!(
+                "{} already exists and isn't a directory",
+                destination.display()
+            ));
+        } else {
+            problems.extend(check_moves(&present, destination));
+        }
+        refuse(problems, "nest");
 
-        if lock_to.exists() {
-            log::debug!("{} -> {}", lock_to.display(), lock_from.display());
-            if !dryrun {
-                fs::rename(lock_to, lock_from).expect("Failed to move flake.lock");
-            }
+        let mut plan = FsPlan::journaled("nest");
+        plan.mkdir(destination);
+        plan_moves(&mut plan, &present, Path::new(""), destination);
+        let git = GitMoves::new(&present, Path::new(""), destination);
+
+        let relocate = |p: &Path| match present.iter().any(|m| p.starts_with(m)) {
+            true => destination.join(p),
+            false => p.to_path_buf(),
+        };
+        plan_rewrite(&mut plan, Path::new(""), destination, relocate, dryrun);
+
+        plan.run(dryrun).expect("Failed to nest flake!");
+        git.apply(dryrun);
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct UnNest {
+    /// Extra file or directory to move back along with the flake, on top of nest_companions in
+    /// the config. Can be given multiple times
+    #[arg(long = "with", value_name = "PATH")]
+    with: Vec<PathBuf>,
+}
+
+impl Actionable for UnNest {
+    fn perform(&self, dryrun: bool) {
+        let source = &nest_dir();
+        let paths = moved_paths(&self.with);
+
+        if !source.join("flake.nix").is_file() {
+            refuse(
+                vec![format!(
+                    "There is no {}",
+                    source.join("flake.nix").display()
+                )],
+                "unnest",
+            );
         }
 
-        log::debug!("Removing dir: {}", source.display());
-        if !dryrun {
-            fs::remove_dir(source).expect("Failed to remove ./flake");
+        let present: Vec<&PathBuf> = paths
+            .iter()
+            .filter(|p| plan::exists(&source.join(p)))
+            .collect();
+
+        let mut problems: Vec<String> = self
+            .with
+            .iter()
+            .filter(|p| !plan::exists(&source.join(p)))
+            .map(|p| format!("{} doesn't exist", source.join(p).display()))
+            .collect();
+        problems.extend(check_moves(&present, Path::new("")));
+
+        let leftovers = leftovers(source, Path::new(""), &present)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", source.display(), e));
+        if !leftovers.is_empty() {
+            let names: Vec<String> = leftovers.iter().map(|p| p.display().to_string()).collect();
+            problems.push(format!(
+                "{} contains files that wouldn't be moved: {}. Pass them with --with or add them to nest_companions",
+                source.display(),
+                names.join(", ")
+            ));
         }
+        refuse(problems, "unnest");
+
+        let mut plan = FsPlan::journaled("unnest");
+        plan_moves(&mut plan, &present, source, Path::new(""));
+        let git = GitMoves::new(&present, source, Path::new(""));
+
+        let relocate = |p: &Path| match p.strip_prefix(source) {
+            Ok(relative) if present.iter().any(|m| relative.starts_with(m)) => {
+                relative.to_path_buf()
+            }
+            _ => p.to_path_buf(),
+        };
+        plan_rewrite(&mut plan, source, Path::new(""), relocate, dryrun);
+        plan.remove(source);
+        plan.run(dryrun).expect("Failed to unnest flake!");
+        git.apply(dryrun);
     }
 }
@@ -0,0 +1,484 @@
+use std::{
+    fmt, fs,
+    os::unix,
+    path::{self, Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, bail};
+
+use crate::journal::{self, Step};
+
+/// A single change to the filesystem
+#[derive(Debug, Clone)]
+pub enum Operation {
+    /// Creates a directory along with any missing parents
+    Mkdir(PathBuf),
+    /// Copies a file along with its permissions, overwriting the destination. Symlinks are
+    /// copied as links
+    Copy { from: PathBuf, to: PathBuf },
+    /// Writes contents to a file, overwriting the destination
+    Write {
+        to: PathBuf,
+        contents: Vec<u8>,
+        permissions: Option<fs::Permissions>,
+    },
+    /// Renames a file or directory. Refuses to overwrite the destination
+    Move { from: PathBuf, to: PathBuf },
+    /// Removes a file or an entire directory
+    Remove(PathBuf),
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Mkdir(path) => write!(f, "mkdir   {}", path.display()),
+            Operation::Copy { from, to } => {
+                write!(f, "copy    {} -> {}", from.display(), to.display())
+            }
+            Operation::Write { to, contents, .. } => {
+                write!(f, "write   {} ({} bytes)", to.display(), contents.len())
+            }
+            Operation::Move { from, to } => {
+                write!(f, "move    {} -> {}", from.display(), to.display())
+            }
+            Operation::Remove(path) => write!(f, "remove  {}", path.display()),
+        }
+    }
+}
+
+/// A list of filesystem operations that is either printed (dryrun) or applied as a whole
+#[derive(Debug, Default)]
+pub struct FsPlan {
+    operations: Vec<Operation>,
+    /// Command recorded in the undo journal when the plan is applied
+    journal: Option<String>,
+}
+
+impl fmt::Display for FsPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.operations.is_empty() {
+            return writeln!(f, "Nothing to do.");
+        }
+
+        for operation in &self.operations {
+            writeln!(f, "{}", operation)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FsPlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a plan that can be reverted with `nf undo` once applied
+    pub fn journaled<S: Into<String>>(command: S) -> Self {
+        Self {
+            operations: Vec::new(),
+            journal: Some(command.into()),
+        }
+    }
+
+    pub fn push(&mut self, operation: Operation) {
+        self.operations.push(operation);
+    }
+
+    pub fn mkdir<P: Into<PathBuf>>(&mut self, path: P) {
+        self.push(Operation::Mkdir(path.into()));
+    }
+
+    pub fn copy<P: Into<PathBuf>, Q: Into<PathBuf>>(&mut self, from: P, to: Q) {
+        self.push(Operation::Copy {
+            from: from.into(),
+            to: to.into(),
+        });
+    }
+
+    pub fn write<P: Into<PathBuf>>(&mut self, to: P, contents: Vec<u8>) {
+        self.push(Operation::Write {
+            to: to.into(),
+            contents,
+            permissions: None,
+        });
+    }
+
+    /// Like write, but also sets the permissions of the file
+    pub fn write_as<P: Into<PathBuf>>(
+        &mut self,
+        to: P,
+        contents: Vec<u8>,
+        permissions: fs::Permissions,
+    ) {
+        self.push(Operation::Write {
+            to: to.into(),
+            contents,
+            permissions: Some(permissions),
+        });
+    }
+
+    pub fn rename<P: Into<PathBuf>, Q: Into<PathBuf>>(&mut self, from: P, to: Q) {
+        self.push(Operation::Move {
+            from: from.into(),
+            to: to.into(),
+        });
+    }
+
+    pub fn remove<P: Into<PathBuf>>(&mut self, path: P) {
+        self.push(Operation::Remove(path.into()));
+    }
+
+    /// Plans a recursive copy of a directory, parents before their contents. Symlinks are
+    /// copied as links rather than followed
+    pub fn copy_dir(&mut self, from: &Path, to: &Path) -> Result<()> {
+        self.mkdir(to);
+
+        let mut entries = fs::read_dir(from)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort();
+
+        for path in entries {
+            let dest = to.join(path.file_name().unwrap());
+            if fs::symlink_metadata(&path)?.is_dir() {
+                self.copy_dir(&path, &dest)?;
+            } else {
+                self.copy(path, dest);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies every operation in order. If any of them fails, everything already applied is
+    /// rolled back before the error is returned.
+    pub fn apply(&self) -> Result<()> {
+        let entry = match &self.journal {
+            Some(command) => Some(journal::Entry::create(command)?),
+            None => None,
+        };
+
+        let backup_dir = match &entry {
+            Some(e) => e.backup_dir(),
+            None => temporary_backup_dir(),
+        };
+        let mut transaction = Transaction::new(backup_dir);
+
+        for operation in &self.operations {
+            log::debug!("Applying: {}", operation);
+            if let Err(e) = transaction.apply(operation) {
+                log::error!("{} failed, rolling back", operation);
+                if let Err(rollback) = journal::revert(&mut transaction.undo) {
+                    log::error!("Rollback failed: {:?}", rollback);
+                }
+
+                match entry {
+                    Some(entry) => entry.discard(),
+                    None => transaction.cleanup(),
+                }
+                return Err(e.context(format!("Failed to {}", operation)));
+            }
+        }
+
+        // The changes are made at this point, so journal problems aren't failures of the plan
+        match entry {
+            Some(mut entry) => {
+                entry.steps = transaction.undo;
+                if let Err(e) = entry.save() {
+                    eprintln!(
+                        "Warning: the changes can't be undone, saving them failed: {:?}",
+                        e
+                    );
+                }
+                if let Err(e) = journal::prune() {
+                    eprintln!("Warning: couldn't prune the undo journal: {:?}", e);
+                }
+            }
+            None => transaction.cleanup(),
+        }
+
+        Ok(())
+    }
+
+    /// Prints the plan to stdout if dryrun == true, otherwise applies it
+    pub fn run(&self, dryrun: bool) -> Result<()> {
+        if dryrun {
+            print!("{}", self);
+            Ok(())
+        } else {
+            self.apply()
+        }
+    }
+}
+
+/// Unique directory for backups that only need to live as long as the plan is being applied
+fn temporary_backup_dir() -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    std::env::temp_dir().join(format!("nf-{}-{}", process::id(), nanos))
+}
+
+/// Keeps track of applied operations so they can be reversed
+struct Transaction {
+    backup_dir: PathBuf,
+    backups: usize,
+    undo: Vec<Step>,
+}
+
+impl Transaction {
+    fn new(backup_dir: PathBuf) -> Self {
+        Self {
+            backup_dir,
+            backups: 0,
+            undo: Vec::new(),
+        }
+    }
+
+    /// Moves an existing path out of the way so it can be restored later
+    fn backup(&mut self, path: &Path) -> Result<()> {
+        fs::create_dir_all(&self.backup_dir)?;
+        let backup = self.backup_dir.join(self.backups.to_string());
+        self.backups += 1;
+
+        move_path(path, &backup)?;
+        self.undo.push(Step::Restore {
+            path: path::absolute(path)?,
+            backup: path::absolute(backup)?,
+            hash: None,
+        });
+
+        Ok(())
+    }
+
+    fn apply(&mut self, operation: &Operation) -> Result<()> {
+        match operation {
+            Operation::Mkdir(path) => {
+                // Relative paths end in "" when walking their ancestors
+                let path = path::absolute(path)?;
+                let mut missing: Vec<PathBuf> = path
+                    .ancestors()
+                    .take_while(|p| !exists(p))
+                    .map(Path::to_path_buf)
+                    .collect();
+                missing.reverse();
+
+                fs::create_dir_all(&path)?;
+                self.undo.extend(
+                    missing
+                        .into_iter()
+                        .map(|path| Step::Created { path, hash: None }),
+                );
+            }
+            Operation::Copy { from, to } => {
+                self.replace(to)?;
+                copy_file(from, to)?;
+                self.record_hash();
+            }
+            Operation::Write {
+                to,
+                contents,
+                permissions,
+            } => {
+                self.replace(to)?;
+                fs::write(to, contents)?;
+                if let Some(permissions) = permissions {
+                    fs::set_permissions(to, permissions.clone())?;
+                }
+                self.record_hash();
+            }
+            Operation::Move { from, to } => {
+                if exists(to) {
+                    bail!("{} already exists", to.display());
+                }
+
+                fs::rename(from, to)?;
+                self.undo.push(Step::Moved {
+                    from: path::absolute(from)?,
+                    to: path::absolute(to)?,
+                });
+            }
+            Operation::Remove(path) => {
+                if !exists(path) {
+                    bail!("{} does not exist", path.display());
+                }
+
+                self.backup(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Backs up a file that is about to be overwritten, or records that it will be created
+    fn replace(&mut self, to: &Path) -> Result<()> {
+        if exists(to) {
+            self.backup(to)?;
+        } else {
+            self.undo.push(Step::Created {
+                path: path::absolute(to)?,
+                hash: None,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Remembers the contents of the file that the last step created or overwrote, so that
+    /// undo can tell whether it was edited since
+    fn record_hash(&mut self) {
+        if let Some(Step::Created { path, hash } | Step::Restore { path, hash, .. }) =
+            self.undo.last_mut()
+        {
+            *hash = journal::file_hash(path);
+        }
+    }
+
+    fn cleanup(&self) {
+        if self.backup_dir.exists()
+            && let Err(e) = fs::remove_dir_all(&self.backup_dir)
+        {
+            log::warn!("Couldn't clean up {:?}: {}", self.backup_dir, e);
+        }
+    }
+}
+
+/// Like Path::exists, but also true for broken symlinks
+pub fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+/// Removes a file or directory without following symlinks
+pub fn remove_path(path: &Path) -> Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Copies a file with its permissions, or recreates a symlink
+fn copy_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::symlink_metadata(from)?.is_symlink() {
+        unix::fs::symlink(fs::read_link(from)?, to)?;
+    } else {
+        fs::copy(from, to)?;
+    }
+
+    Ok(())
+}
+
+/// Recursively copies a file or directory
+fn copy_recursive(from: &Path, to: &Path) -> Result<()> {
+    if fs::symlink_metadata(from)?.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        copy_file(from, to)?;
+    }
+
+    Ok(())
+}
+
+/// Renames a path, falling back to copying when crossing filesystems
+pub fn move_path(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_err() {
+        copy_recursive(from, to)?;
+        remove_path(from)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::FsPlan;
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("nf-plan-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn apply() {
+        let dir = scratch("apply");
+        fs::write(dir.join("a"), "a").unwrap();
+        fs::write(dir.join("old"), "old").unwrap();
+
+        let mut plan = FsPlan::new();
+        plan.mkdir(dir.join("nested/deeper"));
+        plan.copy(dir.join("a"), dir.join("nested/a"));
+        plan.write(dir.join("old"), b"new".to_vec());
+        plan.rename(dir.join("a"), dir.join("b"));
+        plan.apply().unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("nested/a")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dir.join("old")).unwrap(), "new");
+        assert!(dir.join("nested/deeper").is_dir());
+        assert!(dir.join("b").is_file());
+        assert!(!dir.join("a").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn copy_preserves() {
+        use std::os::unix::fs::{PermissionsExt, symlink};
+
+        let dir = scratch("copy");
+        fs::write(dir.join("script"), "#!/bin/sh").unwrap();
+        fs::set_permissions(dir.join("script"), fs::Permissions::from_mode(0o755)).unwrap();
+        symlink("script", dir.join("link")).unwrap();
+
+        let mut plan = FsPlan::new();
+        plan.copy_dir(&dir, &dir.join("copy")).unwrap();
+        plan.apply().unwrap();
+
+        let mode = fs::metadata(dir.join("copy/script"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert_eq!(
+            fs::read_link(dir.join("copy/link")).unwrap(),
+            std::path::Path::new("script")
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rollback() {
+        let dir = scratch("rollback");
+        fs::write(dir.join("a"), "a").unwrap();
+        fs::write(dir.join("keep"), "keep").unwrap();
+
+        let mut plan = FsPlan::new();
+        plan.mkdir(dir.join("nested"));
+        plan.write(dir.join("keep"), b"overwritten".to_vec());
+        plan.remove(dir.join("a"));
+        plan.rename(dir.join("missing"), dir.join("nested/missing"));
+        assert!(plan.apply().is_err());
+
+        assert_eq!(fs::read_to_string(dir.join("a")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dir.join("keep")).unwrap(), "keep");
+        assert!(!dir.join("nested").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
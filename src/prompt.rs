@@ -0,0 +1,39 @@
+use std::io::{self, Write};
+
+/// Prints a prompt to stderr and reads a trimmed line from stdin
+fn read_answer(prompt: &str) -> io::Result<String> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("No answer given for: {}", prompt.trim()),
+        ));
+    }
+
+    Ok(answer.trim().to_string())
+}
+
+/// Asks the user for a value on stdin. An empty answer picks the default, if there is one.
+pub fn ask(question: &str, default: Option<&str>) -> io::Result<String> {
+    loop {
+        let answer = match default {
+            Some(d) => read_answer(&format!("{} [{}]: ", question, d))?,
+            None => read_answer(&format!("{}: ", question))?,
+        };
+
+        match (answer.is_empty(), default) {
+            (false, _) => return Ok(answer),
+            (true, Some(d)) => return Ok(d.to_string()),
+            (true, None) => continue,
+        }
+    }
+}
+
+/// Asks a yes/no question on stdin, defaulting to no
+pub fn confirm(question: &str) -> io::Result<bool> {
+    let answer = read_answer(&format!("{} [y/N]: ", question))?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
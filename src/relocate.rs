@@ -0,0 +1,259 @@
+use std::path::{Component, Path, PathBuf};
+
+use regex::Regex;
+
+/// Where the scanner is in a nix file
+enum Context {
+    /// Plain code, counting the braces opened since it started so that the end of an
+    /// interpolation can be found
+    Code(usize),
+    /// Inside "..."
+    String,
+    /// Inside ''...''
+    IndentedString,
+}
+
+/// Byte ranges in a file
+type Spans = Vec<(usize, usize)>;
+
+fn is_path_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"._-+/".contains(&c)
+}
+
+/// Finds the byte ranges of relative path literals, like ./src or ../Cargo.toml, outside of
+/// strings and comments. Paths that continue with an interpolation are returned separately.
+fn find_paths(text: &str) -> (Spans, Spans) {
+    let b = text.as_bytes();
+    let at = |i: usize, s: &str| b[i..].starts_with(s.as_bytes());
+
+    let mut paths = Vec::new();
+    let mut interpolated = Vec::new();
+    let mut stack = vec![Context::Code(0)];
+    let mut i = 0;
+
+    while i < b.len() {
+        let nested = stack.len() > 1;
+        match stack.last_mut().unwrap() {
+            Context::Code(depth) => match b[i] {
+                b'#' => {
+                    while i < b.len() && b[i] != b'\n' {
+                        i += 1;
+                    }
+                }
+                b'/' if at(i, "/*") => {
+                    i = text[i + 2..].find("*/").map_or(b.len(), |e| i + e + 4);
+                }
+                b'"' => {
+                    stack.push(Context::String);
+                    i += 1;
+                }
+                b'\'' if at(i, "''") => {
+                    stack.push(Context::IndentedString);
+                    i += 2;
+                }
+                b'{' => {
+                    *depth += 1;
+                    i += 1;
+                }
+                b'}' => {
+                    if *depth == 0 && nested {
+                        stack.pop();
+                    } else {
+                        *depth = depth.saturating_sub(1);
+                    }
+                    i += 1;
+                }
+                c if is_path_char(c) || c == b'\'' => {
+                    let start = i;
+                    while i < b.len() && (is_path_char(b[i]) || b[i] == b'\'') {
+                        i += 1;
+                    }
+
+                    let token = &text[start..i];
+                    if !token.starts_with("./") && !token.starts_with("../") {
+                        continue;
+                    } else if at(i, "${") {
+                        interpolated.push((start, i));
+                    } else if !token.ends_with('/') {
+                        paths.push((start, i));
+                    }
+                }
+                _ => i += 1,
+            },
+            Context::String => match b[i] {
+                b'\\' => i += 2,
+                b'"' => {
+                    stack.pop();
+                    i += 1;
+                }
+                b'$' if at(i, "${") => {
+                    stack.push(Context::Code(0));
+                    i += 2;
+                }
+                _ => i += 1,
+            },
+            Context::IndentedString => {
+                if at(i, "''\\") {
+                    i += 4;
+                } else if at(i, "'''") || at(i, "''$") {
+                    i += 3;
+                } else if at(i, "''") {
+                    stack.pop();
+                    i += 2;
+                } else if at(i, "${") {
+                    stack.push(Context::Code(0));
+                    i += 2;
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    (paths, interpolated)
+}
+
+/// Resolves . and .. without touching the filesystem. Leading .. are kept.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(out.components().next_back(), Some(Component::Normal(_))) {
+                    out.pop();
+                } else {
+                    out.push("..");
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Formats the path to target from dir as a nix path literal. Both are normalized and relative
+/// to the same root.
+fn literal(dir: &Path, target: &Path) -> String {
+    let dir: Vec<_> = dir.components().collect();
+    let target: Vec<_> = target.components().collect();
+    let common = dir.iter().zip(&target).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    (common..dir.len()).for_each(|_| relative.push(".."));
+    target[common..].iter().for_each(|c| relative.push(c));
+
+    match relative.to_string_lossy().as_ref() {
+        "" => "./.".to_string(),
+        ".." => "./..".to_string(),
+        r if r.starts_with("..") => r.to_string(),
+        r => format!("./{}", r),
+    }
+}
+
+/// A nix file with its relative paths rewritten for a new location
+pub struct Rewrite {
+    pub text: String,
+    /// Constructs that couldn't be rewritten safely, with their line numbers
+    pub warnings: Vec<String>,
+}
+
+/// Rewrites the relative path literals of a nix file that moves from one directory to another,
+/// both relative to the project root. relocate maps where a path pointed to before the move to
+/// where it will be after, so that files moving along keep being found.
+pub fn rewrite<F>(text: &str, from: &Path, to: &Path, relocate: F) -> Rewrite
+where
+    F: Fn(&Path) -> PathBuf,
+{
+    let line = |offset: usize| text[..offset].matches('\n').count() + 1;
+    let (paths, interpolated) = find_paths(text);
+
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, end) in paths {
+        let target = relocate(&normalize(&from.join(&text[start..end])));
+
+        out.push_str(&text[last..start]);
+        out.push_str(&literal(to, &normalize(&target)));
+        last = end;
+    }
+    out.push_str(&text[last..]);
+
+    let mut warnings: Vec<String> = interpolated
+        .into_iter()
+        .map(|(start, end)| {
+            format!(
+                "line {}: {} is followed by an interpolation and wasn't rewritten",
+                line(start),
+                &text[start..end]
+            )
+        })
+        .collect();
+
+    // self is the directory of the flake, so it moves along with it
+    let self_path =
+        Regex::new(r"=\s*self\s*;|\$\{\s*self\s*\}|\bself\s*\+|\bself\.outPath\b").unwrap();
+    warnings.extend(self_path.find_iter(text).map(|m| {
+        format!(
+            "line {}: `{}` uses self as a path, which now points to {}",
+            line(m.start()),
+            m.as_str().trim_end_matches(';').trim(),
+            literal(Path::new(""), to)
+        )
+    }));
+
+    Rewrite {
+        text: out,
+        warnings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{find_paths, rewrite};
+
+    #[test]
+    fn paths() {
+        let text = r#"{ a = ./src; b = "./no ${./yes}"; c = ''./no ${../also}''; # ./comment
+            d = x./y; e = ./dir/${name}; /* ./comment */ f = ./.; }"#;
+        let (paths, interpolated) = find_paths(text);
+        let found: Vec<&str> = paths.iter().map(|&(s, e)| &text[s..e]).collect();
+
+        assert_eq!(found, vec!["./src", "./yes", "../also", "./."]);
+        assert_eq!(&text[interpolated[0].0..interpolated[0].1], "./dir/");
+    }
+
+    #[test]
+    fn nest_and_unnest() {
+        let flat = "src = ./.; lock = ./Cargo.lock; module = ./nix/mod.nix; up = ../shared;";
+        let nested = "src = ./..; lock = ../Cargo.lock; module = ./nix/mod.nix; up = ../../shared;";
+        let nest = Path::new("flake");
+
+        let nested_rewrite = rewrite(flat, Path::new(""), nest, |p| {
+            if p.starts_with("nix") {
+                nest.join(p)
+            } else {
+                p.to_path_buf()
+            }
+        });
+        assert_eq!(nested_rewrite.text, nested);
+
+        let flat_rewrite = rewrite(nested, nest, Path::new(""), |p| {
+            p.strip_prefix(nest).map_or(p.to_path_buf(), PathBuf::from)
+        });
+        assert_eq!(flat_rewrite.text, flat);
+    }
+
+    #[test]
+    fn warnings() {
+        let text = "{\n  src = self;\n  other = self.packages;\n}";
+        let warnings = rewrite(text, Path::new(""), Path::new("flake"), Path::to_path_buf).warnings;
+
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("line 2:"));
+    }
+}
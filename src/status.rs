@@ -0,0 +1,222 @@
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use serde::Serialize;
+
+use crate::{
+    cli::{Actionable, print_json},
+    config::manager::get_config_dir,
+    expansions::processer::shell_depth,
+    flake::{self, Location},
+    git,
+    lock::{Input, LockFile},
+    templates::record::RECORD_FILE,
+    time,
+};
+
+/// Whether git can see the flake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Git {
+    Tracked,
+    Untracked,
+    NoRepository,
+}
+
+#[derive(Debug, Serialize)]
+struct Lock {
+    path: PathBuf,
+    /// Seconds since the newest locked input was last modified upstream
+    age: Option<u64>,
+    inputs: Vec<Input>,
+}
+
+#[derive(Debug, Serialize)]
+struct Flake {
+    path: PathBuf,
+    location: Location,
+    git: Git,
+    lock: Option<Lock>,
+}
+
+#[derive(Debug, Serialize)]
+struct Shell {
+    /// Value of IN_NIX_SHELL, set by nix develop
+    kind: Option<String>,
+    depth: usize,
+}
+
+/// Everything nf status reports
+#[derive(Debug, Serialize)]
+struct Report {
+    flake: Option<Flake>,
+    shell: Shell,
+    config_files: Vec<PathBuf>,
+    /// Record of the template the project was created from
+    template_record: Option<PathBuf>,
+}
+
+impl Report {
+    fn new() -> Self {
+        let flake = flake::find().map(|found| {
+            let git = if !git::is_work_tree(&found.dir) {
+                Git::NoRepository
+            } else if git::is_tracked(&found.dir, Path::new("flake.nix")) {
+                Git::Tracked
+            } else {
+                Git::Untracked
+            };
+
+            let lock_file = found.lock_file();
+            let lock = lock_file.is_file().then(|| {
+                let inputs = match LockFile::load(&lock_file) {
+                    Ok(lock) => lock.inputs(),
+                    Err(e) => {
+                        log::warn!("{:?}", e);
+                        Vec::new()
+                    }
+                };
+
+                Lock {
+                    // The same lastModified that nf lock outdated goes by
+                    age: inputs.iter().filter_map(Input::age).min(),
+                    inputs,
+                    path: lock_file,
+                }
+            });
+
+            Flake {
+                path: found.nix_file(),
+                location: found.location,
+                git,
+                lock,
+            }
+        });
+
+        let config_files = [get_config_dir().join("config.toml")]
+            .into_iter()
+            .filter(|p| p.is_file())
+            .collect();
+        let template_record = Some(PathBuf::from(RECORD_FILE)).filter(|p| p.is_file());
+
+        Self {
+            flake,
+            shell: Shell {
+                kind: std::env::var("IN_NIX_SHELL").ok(),
+                depth: shell_depth(),
+            },
+            config_files,
+            template_record,
+        }
+    }
+
+    fn print(&self) {
+        match &self.flake {
+            Some(flake) => {
+                println!(
+                    "Flake:  {} ({})",
+                    flake.path.display(),
+                    flake.location.name()
+                );
+                println!(
+                    "Git:    {}",
+                    match flake.git {
+                        Git::Tracked => "tracked",
+                        Git::Untracked => "untracked, nix won't see it until it's added",
+                        Git::NoRepository => "not in a git repository",
+                    }
+                );
+            }
+            None => println!("Flake:  none found"),
+        }
+
+        if let Some(lock) = self.flake.as_ref().and_then(|f| f.lock.as_ref()) {
+            let age = lock
+                .age
+                .map(|age| format!(" (newest input from {} ago)", time::human_age(age)))
+                .unwrap_or_default();
+            println!("Lock:   {}{}", lock.path.display(), age);
+
+            for input in &lock.inputs {
+                let detail = match &input.follows {
+                    Some(follows) => format!("follows {}", follows),
+                    None => format!(
+                        "{} {} {}",
+                        input.source.as_deref().unwrap_or(&input.kind),
+                        input.short_rev().unwrap_or("-"),
+                        input
+                            .age()
+                            .map(|age| format!("({} ago)", time::human_age(age)))
+                            .unwrap_or_default()
+                    ),
+                };
+                println!("  {:<16}{}", input.name, detail.trim_end());
+            }
+        } else if self.flake.is_some() {
+            println!("Lock:   none");
+        }
+
+        match (&self.shell.kind, self.shell.depth) {
+            (_, 0) => println!("Shell:  not in a nix shell"),
+            (Some(kind), depth) => println!("Shell:  {} nix shell, {} deep", kind, depth),
+            (None, depth) => println!("Shell:  nix shell, {} deep", depth),
+        }
+
+        println!("Config:");
+        self.config_files
+            .iter()
+            .for_each(|f| println!("  {}", f.display()));
+
+        if let Some(record) = &self.template_record {
+            println!("Template record: {}", record.display());
+        }
+    }
+
+    /// One short line, e.g. "nested 3d 1", for shell prompts. Untracked flakes get a !
+    fn prompt(&self) -> String {
+        let mut parts = Vec::new();
+
+        if let Some(flake) = &self.flake {
+            let untracked = if flake.git == Git::Untracked { "!" } else { "" };
+            parts.push(format!("{}{}", flake.location.name(), untracked));
+
+            if let Some(age) = flake.lock.as_ref().and_then(|l| l.age) {
+                parts.push(time::short_age(age));
+            }
+        }
+
+        if self.shell.depth > 0 {
+            parts.push(self.shell.depth.to_string());
+        }
+
+        parts.join(" ")
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct Status {
+    /// Prints the status as json
+    #[arg(long, default_value_t = false, conflicts_with = "prompt")]
+    json: bool,
+
+    /// Prints a single compact line for shell prompts, or nothing outside of a flake
+    #[arg(long, default_value_t = false)]
+    prompt: bool,
+}
+
+impl Actionable for Status {
+    fn perform(&self, _dryrun: bool) {
+        let report = Report::new();
+
+        if self.json {
+            print_json(&report);
+        } else if self.prompt {
+            let prompt = report.prompt();
+            if !prompt.is_empty() {
+                println!("{}", prompt);
+            }
+        } else {
+            report.print();
+        }
+    }
+}
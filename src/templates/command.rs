@@ -0,0 +1,251 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, Subcommand};
+use serde::Serialize;
+
+use crate::{
+    cli::{Actionable, print_json},
+    init,
+};
+
+use super::{NEST_DIR, Operations, Template, manifest::Variable};
+
+/// A path of the template, or one that init would create
+#[derive(Debug, Serialize)]
+struct File {
+    /// Relative to the template, or to the CWD as placed by init with the default values of the
+    /// placeholders. Directories end in a /
+    path: String,
+    /// The template in the extends chain that provides it
+    layer: String,
+}
+
+/// Everything worth knowing about a template, as printed by list and show
+#[derive(Debug, Serialize)]
+struct Summary<'a> {
+    name: &'a str,
+    path: &'a PathBuf,
+    description: Option<String>,
+    extends: Option<&'a String>,
+    tags: &'a [String],
+    nested: bool,
+    /// The layout of the template itself
+    files: Vec<File>,
+    /// What init would create with the current config
+    created: Vec<File>,
+    variables: &'a BTreeMap<String, Variable>,
+    hooks: &'a [String],
+}
+
+/// The destinations of operations, with whether they are directories and their layer. Parents
+/// that only placement creates, e.g. the nest dir of a flat template, are added as well.
+fn files(operations: Operations, layers: &HashMap<PathBuf, String>) -> Vec<File> {
+    let mut entries: BTreeMap<PathBuf, (bool, String)> = operations
+        .into_iter()
+        .map(|(from, to)| (to, (from.is_dir(), layers[&from].clone())))
+        .collect();
+
+    let parents: Vec<(PathBuf, String)> = entries
+        .iter()
+        .flat_map(|(to, (_, layer))| {
+            to.ancestors()
+                .skip(1)
+                .filter(|p| !p.as_os_str().is_empty())
+                .map(move |p| (p.to_path_buf(), layer.clone()))
+        })
+        .collect();
+    for (parent, layer) in parents {
+        entries.entry(parent).or_insert((true, layer));
+    }
+
+    entries
+        .into_iter()
+        .map(|(to, (is_dir, layer))| {
+            let to = to.to_string_lossy();
+            let path = if is_dir {
+                format!("{}/", to)
+            } else {
+                to.to_string()
+            };
+
+            File { path, layer }
+        })
+        .collect()
+}
+
+impl<'a> Summary<'a> {
+    fn new(template: &'a Template) -> Self {
+        let layers: HashMap<PathBuf, String> = template
+            .map_files()
+            .expect("Couldn't map template files!")
+            .into_values()
+            .map(|source| (source.path, source.layer))
+            .collect();
+        let operations = template
+            .map_operations()
+            .expect("Couldn't map template files!");
+        let nested = operations
+            .iter()
+            .any(|(_, to)| to == &Path::new(NEST_DIR).join("flake.nix"));
+        let created = init::preview_operations(template).expect("Couldn't map template files!");
+
+        Self {
+            name: &template.name,
+            path: &template.path,
+            description: template.description(),
+            extends: template.manifest.extends.as_ref(),
+            tags: &template.manifest.tags,
+            nested,
+            files: files(operations, &layers),
+            created: files(created, &layers),
+            variables: &template.manifest.variables,
+            hooks: &template.manifest.hooks,
+        }
+    }
+
+    /// Prints the name, description and layout on one line
+    fn print_header(&self) {
+        let mut header = self.name.to_string();
+        if self.nested {
+            header.push_str(" (nested)");
+        }
+        if let Some(description) = &self.description {
+            header.push_str(" - ");
+            header.push_str(description);
+        }
+
+        println!("{}", header);
+    }
+
+    /// Prints the files as an indented tree
+    fn print_tree(&self) {
+        for file in self.files.iter().map(|f| &f.path) {
+            let trimmed = file.trim_end_matches('/');
+            let depth = trimmed.matches('/').count();
+            let name = trimmed.rsplit('/').next().unwrap_or(trimmed);
+            let suffix = if file.ends_with('/') { "/" } else { "" };
+
+            println!("{}{}{}", "  ".repeat(depth + 1), name, suffix);
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct List {
+    /// Prints the templates as json
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+impl Actionable for List {
+    fn perform(&self, _dryrun: bool) {
+        let templates = Template::all().expect("Couldn't load templates!");
+        let summaries: Vec<Summary> = templates.iter().map(Summary::new).collect();
+
+        if self.json {
+            print_json(&summaries);
+            return;
+        }
+
+        for summary in summaries {
+            summary.print_header();
+            summary.print_tree();
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct Show {
+    /// Name of the template in <config_dir>/templates/
+    template: String,
+
+    /// Prints the template as json
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+impl Actionable for Show {
+    fn perform(&self, _dryrun: bool) {
+        let template = Template::find(&self.template).expect("Couldn't load template!");
+        let summary = Summary::new(&template);
+
+        if self.json {
+            print_json(&summary);
+            return;
+        }
+
+        summary.print_header();
+        println!("Path: {}", summary.path.display());
+        if let Some(extends) = summary.extends {
+            println!("Extends: {}", extends);
+        }
+        if !summary.tags.is_empty() {
+            println!("Tags: {}", summary.tags.join(", "));
+        }
+
+        println!("Files init would create:");
+        for file in summary.created.iter().filter(|f| !f.path.ends_with('/')) {
+            if file.layer == summary.name {
+                println!("  {}", file.path);
+            } else {
+                println!("  {} (from {})", file.path, file.layer);
+            }
+        }
+
+        if !summary.variables.is_empty() {
+            println!("Variables:");
+            for (name, variable) in summary.variables {
+                let default = variable
+                    .default
+                    .as_ref()
+                    .map(|d| format!(" [{}]", d))
+                    .unwrap_or_default();
+                let description = variable
+                    .description
+                    .as_ref()
+                    .map(|d| format!(" - {}", d))
+                    .unwrap_or_default();
+
+                println!("  {}{}{}", name, default, description);
+            }
+        }
+
+        if !summary.hooks.is_empty() {
+            println!("Hooks:");
+            summary.hooks.iter().for_each(|h| println!("  {}", h));
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Action {
+    /// Lists every template with its description and files
+    List(List),
+    /// Shows the files a template would create, along with its manifest
+    Show(Show),
+}
+
+impl Actionable for Action {
+    fn perform(&self, dryrun: bool) {
+        match self {
+            Action::List(list) => list.perform(dryrun),
+            Action::Show(show) => show.perform(dryrun),
+        };
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct Templates {
+    /// Template action to perform
+    #[command(subcommand)]
+    action: Action,
+}
+
+impl Actionable for Templates {
+    fn perform(&self, dryrun: bool) {
+        self.action.perform(dryrun);
+    }
+}
@@ -0,0 +1,96 @@
+use std::{collections::BTreeMap, path::Path};
+
+use super::Template;
+
+/// Project manifests, along with the tag of the templates that suit them
+pub const PROJECT_FILES: [(&str, &str); 5] = [
+    ("Cargo.toml", "rust"),
+    ("package.json", "node"),
+    ("pyproject.toml", "python"),
+    ("go.mod", "go"),
+    ("CMakeLists.txt", "cmake"),
+];
+
+/// Used when nothing in the project points to a template
+pub const FALLBACK: &str = "basic";
+
+/// A template picked for a project, along with why it was picked
+#[derive(Debug, PartialEq, Eq)]
+pub struct Detection {
+    pub template: String,
+    pub reason: String,
+}
+
+/// Picks a template for the project in dir. The first project file that is found decides,
+/// through the [detect] mapping of the config, or else through the first template with the
+/// file's tag, either as its name or in its manifest.
+pub fn detect(dir: &Path, mapping: &BTreeMap<String, String>, templates: &[Template]) -> Detection {
+    for (file, tag) in PROJECT_FILES {
+        if !dir.join(file).is_file() {
+            continue;
+        }
+
+        if let Some(template) = mapping.get(file) {
+            return Detection {
+                template: template.clone(),
+                reason: format!("found {}, which [detect] maps to {}", file, template),
+            };
+        }
+
+        if let Some(template) = templates
+            .iter()
+            .find(|t| t.name == tag || t.manifest.tags.iter().any(|t| t == tag))
+        {
+            return Detection {
+                template: template.name.clone(),
+                reason: format!("found {} and {} is tagged {}", file, template.name, tag),
+            };
+        }
+
+        log::info!("Found {}, but no template is tagged {}", file, tag);
+    }
+
+    Detection {
+        template: FALLBACK.to_string(),
+        reason: "no template matches the project".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, path::PathBuf};
+
+    use super::{FALLBACK, detect};
+    use crate::templates::{Template, manifest::Manifest};
+
+    fn template(name: &str, tags: &[&str]) -> Template {
+        Template {
+            name: name.to_string(),
+            path: PathBuf::new(),
+            manifest: Manifest {
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                ..Default::default()
+            },
+            parent: None,
+        }
+    }
+
+    #[test]
+    fn picks() {
+        let dir = std::env::temp_dir().join(format!("nf-detect-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let templates = [template("basic", &[]), template("crane", &["rust"])];
+        let mut mapping = BTreeMap::new();
+        assert_eq!(detect(&dir, &mapping, &templates).template, FALLBACK);
+
+        fs::write(dir.join("Cargo.toml"), "").unwrap();
+        assert_eq!(detect(&dir, &mapping, &templates).template, "crane");
+
+        mapping.insert("Cargo.toml".to_string(), "rust-nightly".to_string());
+        assert_eq!(detect(&dir, &mapping, &templates).template, "rust-nightly");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
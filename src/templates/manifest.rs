@@ -0,0 +1,222 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::placeholders::Variables;
+
+/// Name of the optional manifest file in the root of a template
+pub const MANIFEST_FILE: &str = "template.toml";
+
+/// A variable declared by a template
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Variable {
+    /// Shown when prompting for the variable
+    pub description: Option<String>,
+    pub default: Option<String>,
+    /// Regex that the value must fully match
+    pub pattern: Option<String>,
+    /// If not empty, the value must be one of these
+    pub choices: Vec<String>,
+}
+
+impl Variable {
+    /// Checks a value against the pattern and choices of the variable
+    pub fn validate(&self, name: &str, value: &str) -> Result<()> {
+        if let Some(pattern) = &self.pattern {
+            let regex = Regex::new(&format!("^(?:{})$", pattern))
+                .with_context(|| format!("Invalid pattern for variable {}", name))?;
+
+            if !regex.is_match(value) {
+                bail!(
+                    "\"{}\" isn't a valid value for {}, it must match {}",
+                    value,
+                    name,
+                    pattern
+                );
+            }
+        }
+
+        if !self.choices.is_empty() && !self.choices.iter().any(|c| c == value) {
+            bail!(
+                "\"{}\" isn't a valid value for {}, it must be one of: {}",
+                value,
+                name,
+                self.choices.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Metadata and options of a template, read from its template.toml
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    pub description: Option<String>,
+    /// Name of a template whose files this one builds upon, overriding any that it also has
+    pub extends: Option<String>,
+    pub tags: Vec<String>,
+    pub variables: BTreeMap<String, Variable>,
+    /// Globs of template files that shouldn't be initialized
+    pub exclude: Vec<String>,
+    /// Shell commands run in the initialized directory after init, e.g. "git init"
+    pub hooks: Vec<String>,
+}
+
+impl Manifest {
+    /// Reads the manifest of a template. Templates without one get an empty manifest.
+    pub fn load(template: &Path) -> Result<Self> {
+        let file = template.join(MANIFEST_FILE);
+        if !file.is_file() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&file)?;
+        toml::from_str(&contents).with_context(|| format!("Couldn't parse {}", file.display()))
+    }
+
+    /// Takes on the tags, variables, excludes and hooks of the parent template. Anything this
+    /// manifest declares itself wins, and the parent's hooks run first.
+    pub fn inherit(&mut self, parent: &Manifest) {
+        for tag in &parent.tags {
+            if !self.tags.contains(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
+
+        for (name, variable) in &parent.variables {
+            self.variables
+                .entry(name.clone())
+                .or_insert_with(|| variable.clone());
+        }
+
+        self.exclude.extend(parent.exclude.iter().cloned());
+        self.hooks.splice(0..0, parent.hooks.iter().cloned());
+    }
+
+    /// Default values of the declared variables
+    pub fn defaults(&self) -> Variables {
+        self.variables
+            .iter()
+            .filter_map(|(name, v)| v.default.clone().map(|d| (name.clone(), d)))
+            .collect()
+    }
+
+    /// Descriptions of the declared variables that have one
+    pub fn descriptions(&self) -> Variables {
+        self.variables
+            .iter()
+            .filter_map(|(name, v)| v.description.clone().map(|d| (name.clone(), d)))
+            .collect()
+    }
+
+    /// Validates every resolved value of a declared variable
+    pub fn validate(&self, variables: &Variables) -> Result<()> {
+        for (name, value) in variables {
+            if let Some(variable) = self.variables.get(name) {
+                variable.validate(name, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Builds the matcher for excluded files. The manifest itself is always excluded.
+    pub fn excludes(&self) -> Result<GlobSet> {
+        let mut builder = GlobSetBuilder::new();
+        builder.add(Glob::new(MANIFEST_FILE)?);
+
+        for pattern in &self.exclude {
+            builder.add(
+                Glob::new(pattern)
+                    .with_context(|| format!("Invalid exclude pattern {}", pattern))?,
+            );
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+/// Whether a path relative to the template root, or any of its parents, is excluded
+pub fn is_excluded(excludes: &GlobSet, relative: &Path) -> bool {
+    relative.ancestors().any(|p| excludes.is_match(p))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Manifest, is_excluded};
+
+    const MANIFEST: &str = r#"
+        description = "Rust flake"
+        tags = ["rust"]
+        exclude = ["*.bak", "docs"]
+        hooks = ["git init"]
+
+        [variables.project_name]
+        pattern = "[a-z][a-z0-9-]*"
+
+        [variables.channel]
+        default = "stable"
+        choices = ["stable", "nightly"]
+    "#;
+
+    #[test]
+    fn parse() {
+        let manifest: Manifest = toml::from_str(MANIFEST).unwrap();
+        assert_eq!(manifest.description.as_deref(), Some("Rust flake"));
+        assert_eq!(manifest.defaults().get("channel").unwrap(), "stable");
+        assert!(toml::from_str::<Manifest>("unknown = 1").is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let manifest: Manifest = toml::from_str(MANIFEST).unwrap();
+        let project = &manifest.variables["project_name"];
+        let channel = &manifest.variables["channel"];
+
+        assert!(project.validate("project_name", "my-project").is_ok());
+        assert!(project.validate("project_name", "My Project").is_err());
+        assert!(channel.validate("channel", "nightly").is_ok());
+        assert!(channel.validate("channel", "beta").is_err());
+    }
+
+    #[test]
+    fn inherit() {
+        let parent: Manifest = toml::from_str(MANIFEST).unwrap();
+        let mut child: Manifest = toml::from_str(
+            r#"
+            extends = "rust"
+            tags = ["nightly"]
+            hooks = ["nix flake lock"]
+
+            [variables.channel]
+            default = "nightly"
+            "#,
+        )
+        .unwrap();
+        child.inherit(&parent);
+
+        assert_eq!(child.tags, vec!["nightly", "rust"]);
+        assert_eq!(child.hooks, vec!["git init", "nix flake lock"]);
+        assert_eq!(child.defaults().get("channel").unwrap(), "nightly");
+        assert!(child.variables.contains_key("project_name"));
+    }
+
+    #[test]
+    fn excludes() {
+        let manifest: Manifest = toml::from_str(MANIFEST).unwrap();
+        let excludes = manifest.excludes().unwrap();
+
+        assert!(is_excluded(&excludes, Path::new("template.toml")));
+        assert!(is_excluded(&excludes, Path::new("flake.nix.bak")));
+        assert!(is_excluded(&excludes, Path::new("docs/index.md")));
+        assert!(!is_excluded(&excludes, Path::new("flake.nix")));
+    }
+}
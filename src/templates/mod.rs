@@ -0,0 +1,201 @@
+pub mod command;
+pub mod detect;
+pub mod manifest;
+pub mod placeholders;
+pub mod record;
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::config::manager::map_templates;
+use manifest::Manifest;
+
+/// Directory that nested templates keep their flake files in
+pub const NEST_DIR: &str = "flake";
+
+/// Optional file in the root of a template listing files that shouldn't be initialized, in
+/// gitignore syntax
+pub const IGNORE_FILE: &str = ".nfignore";
+
+/// Recursively gets the full path of every file in a path without following symlinks. .git
+/// directories and everything matched by ignore are skipped.
+fn recursive_read_dir(base: &Path, ignore: &Gitignore) -> Result<Vec<PathBuf>> {
+    let mut out = Vec::new();
+
+    for entry in fs::read_dir(base)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_dir = entry.file_type()?.is_dir();
+
+        if entry.file_name() == ".git" || ignore.matched(&path, is_dir).is_ignore() {
+            continue;
+        }
+
+        if is_dir {
+            out.extend(recursive_read_dir(&path, ignore)?);
+        }
+
+        out.push(path);
+    }
+
+    Ok(out)
+}
+
+/// Paths "from -> to" for initializing a template, ordered by destination so that parent
+/// directories come before their contents
+pub type Operations = Vec<(PathBuf, PathBuf)>;
+
+/// Where a file of a layered template comes from
+#[derive(Debug)]
+pub struct Source {
+    pub path: PathBuf,
+    /// Name of the template in the extends chain that provides the file
+    pub layer: String,
+}
+
+/// A template directory along with its manifest
+#[derive(Debug)]
+pub struct Template {
+    pub name: String,
+    pub path: PathBuf,
+    /// The manifest, including everything inherited from the template it extends
+    pub manifest: Manifest,
+    /// The template this one extends
+    pub parent: Option<Box<Template>>,
+}
+
+impl Template {
+    /// Finds a template by name in <config_dir>/templates/
+    pub fn find(name: &str) -> Result<Self> {
+        let templates = map_templates()?;
+        let path = templates
+            .get(name)
+            .with_context(|| format!("Template {} not found!", name))?;
+
+        Self::load(name, path)
+    }
+
+    pub fn load(name: &str, path: &Path) -> Result<Self> {
+        Self::load_chain(name, path, &mut Vec::new())
+    }
+
+    /// Loads a template along with every template it extends. seen holds the names of the
+    /// templates extending this one, to detect cycles.
+    fn load_chain(name: &str, path: &Path, seen: &mut Vec<String>) -> Result<Self> {
+        seen.push(name.to_string());
+        let mut manifest = Manifest::load(path)?;
+
+        let parent = match &manifest.extends {
+            Some(parent) => {
+                if seen.contains(parent) {
+                    bail!(
+                        "Templates extend each other in a cycle: {} -> {}",
+                        seen.join(" -> "),
+                        parent
+                    );
+                }
+
+                let templates = map_templates()?;
+                let parent_path = templates.get(parent).with_context(|| {
+                    format!("Template {} extends {}, which wasn't found", name, parent)
+                })?;
+
+                let parent = Self::load_chain(parent, parent_path, seen)?;
+                manifest.inherit(&parent.manifest);
+                Some(Box::new(parent))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            path: path.to_path_buf(),
+            manifest,
+            parent,
+        })
+    }
+
+    /// Loads every available template, sorted by name. Broken templates are skipped.
+    pub fn all() -> Result<Vec<Self>> {
+        let templates: BTreeMap<_, _> = map_templates()?.into_iter().collect();
+        let mut out = Vec::with_capacity(templates.len());
+
+        for (name, path) in templates {
+            match Self::load(&name, &path) {
+                Ok(template) => out.push(template),
+                Err(e) => log::warn!("Skipping template {}: {:?}", name, e),
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Maps every path the template creates, relative to the CWD, to where it comes from.
+    /// Files of this template override those of the templates it extends.
+    pub fn map_files(&self) -> Result<BTreeMap<PathBuf, Source>> {
+        let mut out = match &self.parent {
+            Some(parent) => parent.map_files()?,
+            None => BTreeMap::new(),
+        };
+
+        for full in recursive_read_dir(&self.path, &self.ignores()?)? {
+            let relative = full.strip_prefix(&self.path)?;
+            out.insert(
+                relative.to_path_buf(),
+                Source {
+                    path: full.clone(),
+                    layer: self.name.clone(),
+                },
+            );
+        }
+
+        let excludes = self.manifest.excludes()?;
+        out.retain(|relative, _| !manifest::is_excluded(&excludes, relative));
+
+        Ok(out)
+    }
+
+    /// Reads the template's .nfignore, which always ignores itself
+    fn ignores(&self) -> Result<Gitignore> {
+        let mut builder = GitignoreBuilder::new(&self.path);
+        builder.add_line(None, IGNORE_FILE)?;
+
+        let file = self.path.join(IGNORE_FILE);
+        if file.is_file()
+            && let Some(e) = builder.add(&file)
+        {
+            return Err(e).with_context(|| format!("Couldn't read {}", file.display()));
+        }
+
+        Ok(builder.build()?)
+    }
+
+    /// Returns the paths "from -> to" for initializing the template
+    pub fn map_operations(&self) -> Result<Operations> {
+        Ok(self
+            .map_files()?
+            .into_iter()
+            .map(|(to, source)| (source.path, to))
+            .collect())
+    }
+
+    /// The manifest description, falling back to the first line of the README
+    pub fn description(&self) -> Option<String> {
+        if let Some(description) = &self.manifest.description {
+            return Some(description.clone());
+        }
+
+        let readme = fs::read_to_string(self.path.join("README.md")).ok()?;
+        readme
+            .lines()
+            .map(|l| l.trim_start_matches('#').trim())
+            .find(|l| !l.is_empty())
+            .map(|l| l.to_string())
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::IsTerminal,
+    path::Path,
+};
+
+use anyhow::{Result, bail};
+
+use crate::prompt;
+
+/// Values for {{placeholders}}, keyed by placeholder name
+pub type Variables = BTreeMap<String, String>;
+
+/// Whether a placeholder name is made up of valid identifier characters
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Calls f for every placeholder found in text, passing its byte range and name.
+/// Anything between braces that isn't a valid name is left alone.
+fn scan<F: FnMut(usize, usize, &str)>(text: &str, mut f: F) {
+    let mut offset = 0;
+
+    while let Some(start) = text[offset..].find("{{").map(|i| i + offset) {
+        let Some(end) = text[start + 2..].find("}}").map(|i| i + start + 2) else {
+            break;
+        };
+
+        let name = text[start + 2..end].trim();
+        if is_name(name) {
+            f(start, end + 2, name);
+            offset = end + 2;
+        } else {
+            offset = start + 1;
+        }
+    }
+}
+
+/// Returns the names of every placeholder in text
+pub fn find(text: &str) -> BTreeSet<String> {
+    let mut out = BTreeSet::new();
+    scan(text, |_, _, name| {
+        out.insert(name.to_string());
+    });
+    out
+}
+
+/// Replaces every placeholder in text with its value. Fails if a value is missing.
+pub fn render(text: &str, variables: &Variables) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    let mut missing = BTreeSet::new();
+
+    scan(text, |start, end, name| {
+        out.push_str(&text[last..start]);
+        match variables.get(name) {
+            Some(value) => out.push_str(value),
+            None => {
+                missing.insert(name.to_string());
+            }
+        }
+        last = end;
+    });
+    out.push_str(&text[last..]);
+
+    if !missing.is_empty() {
+        bail!("No value for placeholders: {:?}", missing);
+    }
+
+    Ok(out)
+}
+
+/// Parses "key=value" as passed to --set
+pub fn parse_assignment(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, got \"{}\"", s))?;
+
+    if !is_name(key) {
+        return Err(format!("\"{}\" isn't a valid placeholder name", key));
+    }
+
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Default values for the placeholders that the built in templates use
+pub fn builtin_defaults(dir: &Path) -> Variables {
+    let project_name = std::env::current_dir()
+        .ok()
+        .and_then(|d| {
+            d.join(dir)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+        })
+        .unwrap_or_else(|| "project".to_string());
+
+    Variables::from([
+        ("description".to_string(), format!("{} flake", project_name)),
+        ("project_name".to_string(), project_name),
+        ("nixpkgs_branch".to_string(), "nixos-unstable".to_string()),
+    ])
+}
+
+/// Resolves a value for every needed placeholder. Explicitly set values win over prompts, which
+/// win over defaults. Placeholders without any value are prompted for when stdin is a terminal,
+/// along with their description, if they have one.
+pub fn resolve(
+    needed: &BTreeSet<String>,
+    set: &[(String, String)],
+    defaults: &Variables,
+    descriptions: &Variables,
+    interactive: bool,
+) -> Result<Variables> {
+    let set: Variables = set.iter().cloned().collect();
+    let mut out = Variables::new();
+    let mut missing = Vec::new();
+
+    for name in needed {
+        if let Some(value) = set.get(name) {
+            out.insert(name.clone(), value.clone());
+            continue;
+        }
+
+        let default = defaults.get(name);
+        if interactive || (default.is_none() && std::io::stdin().is_terminal()) {
+            let question = match descriptions.get(name) {
+                Some(description) => format!("{} ({})", name, description),
+                None => name.clone(),
+            };
+            out.insert(
+                name.clone(),
+                prompt::ask(&question, default.map(|d| d.as_str()))?,
+            );
+        } else if let Some(default) = default {
+            out.insert(name.clone(), default.clone());
+        } else {
+            missing.push(name.as_str());
+        }
+    }
+
+    if !missing.is_empty() {
+        bail!(
+            "No value for placeholders: {}. Pass them with --set key=value",
+            missing.join(", ")
+        );
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Variables, find, parse_assignment, render};
+
+    #[test]
+    fn find_placeholders() {
+        let found = find("{{ a }} {{b}} {c} {{not valid}} ${x} {{1}} {{_c2}}");
+        assert_eq!(
+            found.into_iter().collect::<Vec<_>>(),
+            vec!["_c2".to_string(), "a".to_string(), "b".to_string()]
+        );
+    }
+
+    #[test]
+    fn render_placeholders() {
+        let variables = Variables::from([
+            ("name".to_string(), "nf".to_string()),
+            ("branch".to_string(), "nixos-unstable".to_string()),
+        ]);
+
+        assert_eq!(
+            render(
+                "{ name = \"{{name}}\"; url = \"nixpkgs/{{ branch }}\"; }",
+                &variables
+            )
+            .unwrap(),
+            "{ name = \"nf\"; url = \"nixpkgs/nixos-unstable\"; }"
+        );
+        assert_eq!(render("{{{name}}}", &variables).unwrap(), "{nf}");
+        assert!(render("{{missing}}", &variables).is_err());
+    }
+
+    #[test]
+    fn assignments() {
+        assert_eq!(
+            parse_assignment("description=a = b").unwrap(),
+            ("description".to_string(), "a = b".to_string())
+        );
+        assert!(parse_assignment("novalue").is_err());
+        assert!(parse_assignment("bad key=value").is_err());
+    }
+}
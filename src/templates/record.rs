@@ -0,0 +1,132 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::placeholders::Variables;
+
+/// Name of the file that init leaves behind to remember which template it used
+pub const RECORD_FILE: &str = ".nf-template.toml";
+
+/// Hex encoded sha256 of some contents
+pub fn hash(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// What a project was initialized from, so that later template changes can be merged in
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Record {
+    pub template: String,
+    /// Values the placeholders were filled in with
+    #[serde(default)]
+    pub variables: Variables,
+    /// Hash of every file as the template last wrote it, keyed by path
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+    /// Contents of every text file as the template last wrote it, the base for merging updates
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub base: BTreeMap<String, String>,
+}
+
+impl Record {
+    pub fn new(template: &str, variables: &Variables) -> Self {
+        Self {
+            template: template.to_string(),
+            variables: variables.clone(),
+            files: BTreeMap::new(),
+            base: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the record in dir, if there is one
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let file = dir.join(RECORD_FILE);
+        if !file.is_file() {
+            return Ok(None);
+        }
+
+        let contents = fs::read_to_string(&file)?;
+        let record = toml::from_str(&contents)
+            .with_context(|| format!("Couldn't parse {}", file.display()))?;
+        Ok(Some(record))
+    }
+
+    /// Whether the file at path still has the contents the template last wrote
+    pub fn is_unmodified(&self, path: &Path, contents: &[u8]) -> bool {
+        self.files.get(path.to_string_lossy().as_ref()) == Some(&hash(contents))
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.contains_key(path.to_string_lossy().as_ref())
+    }
+
+    pub fn insert(&mut self, path: &Path, contents: &[u8]) {
+        let key = path.to_string_lossy().to_string();
+        match std::str::from_utf8(contents) {
+            Ok(text) => self.base.insert(key.clone(), text.to_string()),
+            Err(_) => self.base.remove(&key),
+        };
+        self.files.insert(key, hash(contents));
+    }
+
+    /// Takes over what other recorded about path, for files that weren't written this time
+    pub fn keep(&mut self, other: &Record, path: &Path) {
+        let key = path.to_string_lossy().to_string();
+        if let Some(hash) = other.files.get(&key) {
+            self.files.insert(key.clone(), hash.clone());
+        }
+        if let Some(base) = other.base.get(&key) {
+            self.base.insert(key, base.clone());
+        }
+    }
+
+    /// The contents the template last wrote to a text file
+    pub fn base(&self, path: &Path) -> Option<&str> {
+        self.base
+            .get(path.to_string_lossy().as_ref())
+            .map(String::as_str)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Record, hash};
+    use crate::templates::placeholders::Variables;
+
+    #[test]
+    fn hashes() {
+        assert_eq!(
+            hash(b"nf"),
+            "4ce7bcf6e148d5048b8ebb6760ef1b0b11f67ac0a1d1aad41e31087a1b6a1db7"
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let variables = Variables::from([("project_name".to_string(), "nf".to_string())]);
+        let mut record = Record::new("rust", &variables);
+        record.insert(Path::new("flake.nix"), b"{}");
+
+        let parsed: Record = toml::from_str(&record.to_toml().unwrap()).unwrap();
+        assert_eq!(parsed.template, "rust");
+        assert_eq!(parsed.variables, variables);
+        assert!(parsed.is_unmodified(Path::new("flake.nix"), b"{}"));
+        assert!(!parsed.is_unmodified(Path::new("flake.nix"), b"{ }"));
+        assert!(!parsed.contains(Path::new("flake.lock")));
+        assert_eq!(parsed.base(Path::new("flake.nix")), Some("{}"));
+
+        let mut kept = Record::new("rust", &variables);
+        kept.keep(&parsed, Path::new("flake.nix"));
+        assert!(kept.is_unmodified(Path::new("flake.nix"), b"{}"));
+    }
+}
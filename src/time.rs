@@ -0,0 +1,124 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+
+/// Seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Formats a number of seconds as a rough age, such as "3 days"
+pub fn human_age(seconds: u64) -> String {
+    const UNITS: [(u64, &str); 6] = [
+        (60 * 60 * 24 * 365, "year"),
+        (60 * 60 * 24 * 30, "month"),
+        (60 * 60 * 24 * 7, "week"),
+        (60 * 60 * 24, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+    ];
+
+    for (size, name) in UNITS {
+        if seconds >= size {
+            let count = seconds / size;
+            return format!("{} {}{}", count, name, if count == 1 { "" } else { "s" });
+        }
+    }
+
+    "less than a minute".to_string()
+}
+
+/// Formats a number of seconds as a compact age for prompts, such as "3d"
+pub fn short_age(seconds: u64) -> String {
+    const UNITS: [(u64, &str); 5] = [
+        (60 * 60 * 24 * 365, "y"),
+        (60 * 60 * 24 * 7, "w"),
+        (60 * 60 * 24, "d"),
+        (60 * 60, "h"),
+        (60, "m"),
+    ];
+
+    UNITS
+        .iter()
+        .find(|(size, _)| seconds >= *size)
+        .map(|(size, unit)| format!("{}{}", seconds / size, unit))
+        .unwrap_or_else(|| "0m".to_string())
+}
+
+/// Formats a unix timestamp as a UTC date, such as 2023-11-14
+pub fn date(timestamp: u64) -> String {
+    // civil_from_days from Howard Hinnant's date algorithms
+    let days = (timestamp / (60 * 60 * 24)) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Parses an age such as 12h, 30d, 2w or 1y into seconds. Plain numbers are days.
+pub fn parse_age(age: &str) -> Result<u64> {
+    let split = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    let (count, unit) = age.split_at(split);
+    let count: u64 = count
+        .parse()
+        .with_context(|| format!("{} doesn't start with a number", age))?;
+
+    let size = match unit {
+        "h" => 60 * 60,
+        "" | "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        "y" => 60 * 60 * 24 * 365,
+        _ => bail!("Unknown unit {} in {}, use h, d, w or y", unit, age),
+    };
+
+    Ok(count * size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{date, human_age, parse_age, short_age};
+
+    #[test]
+    fn ages() {
+        assert_eq!(human_age(5), "less than a minute");
+        assert_eq!(human_age(60), "1 minute");
+        assert_eq!(human_age(60 * 60 * 5), "5 hours");
+        assert_eq!(human_age(60 * 60 * 24 * 45), "1 month");
+        assert_eq!(human_age(60 * 60 * 24 * 800), "2 years");
+
+        assert_eq!(short_age(5), "0m");
+        assert_eq!(short_age(60 * 60 * 24 * 3), "3d");
+        assert_eq!(short_age(60 * 60 * 24 * 45), "6w");
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(1700000000), "2023-11-14");
+        assert_eq!(date(951782400), "2000-02-29");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_age("30d").unwrap(), 60 * 60 * 24 * 30);
+        assert_eq!(parse_age("30").unwrap(), 60 * 60 * 24 * 30);
+        assert_eq!(parse_age("12h").unwrap(), 60 * 60 * 12);
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3 months").is_err());
+    }
+}
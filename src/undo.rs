@@ -0,0 +1,81 @@
+use clap::Args;
+
+use crate::{
+    cli::{Actionable, fail},
+    journal, time,
+};
+
+#[derive(Debug, Args)]
+pub struct Undo {
+    /// Lists the commands that can be undone, newest first, instead of undoing anything
+    #[arg(long, default_value_t = false)]
+    list: bool,
+
+    /// Removes created files and restores overwritten ones even if they were edited since
+    #[arg(short, long, default_value_t = false)]
+    force: bool,
+}
+
+impl Undo {
+    fn list(&self) {
+        let entries = journal::entries().expect("Couldn't read the undo journal!");
+        if entries.is_empty() {
+            println!("Nothing to undo.");
+        }
+
+        for (i, entry) in entries.iter().enumerate() {
+            println!(
+                "{}. nf {} ({} ago, {} changes in {})",
+                i + 1,
+                entry.command,
+                time::human_age(time::now().saturating_sub(entry.created)),
+                entry.steps.len(),
+                entry.cwd.display()
+            );
+        }
+    }
+}
+
+impl Actionable for Undo {
+    fn perform(&self, dryrun: bool) {
+        if self.list {
+            self.list();
+            return;
+        }
+
+        let mut entries = journal::entries().expect("Couldn't read the undo journal!");
+        if entries.is_empty() {
+            fail("Nothing to undo.");
+        }
+        let mut entry = entries.remove(0);
+
+        let modified: Vec<_> = entry.steps.iter().filter_map(|s| s.modified()).collect();
+        if !modified.is_empty() && !self.force {
+            let mut msg = format!(
+                "Refusing to undo nf {}, these files were edited since:",
+                entry.command
+            );
+            modified
+                .iter()
+                .for_each(|p| msg.push_str(&format!("\n  {}", p.display())));
+            msg.push_str("\nUse --force to undo them anyway.");
+            fail(&msg);
+        }
+
+        if dryrun {
+            println!("Undoing: nf {}", entry.command);
+            entry.steps.iter().rev().for_each(|s| println!("{}", s));
+            return;
+        }
+
+        if let Err(e) = journal::revert(&mut entry.steps) {
+            entry
+                .save()
+                .expect("Couldn't save what is left to undo to the journal!");
+            panic!("Failed to undo nf {}: {:?}", entry.command, e);
+        }
+
+        println!("Undid: nf {}", entry.command);
+        entry.discard();
+    }
+}